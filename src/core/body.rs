@@ -0,0 +1,145 @@
+use std::io::SeekFrom;
+
+use bytes::Bytes;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+
+#[allow(dead_code)]
+pub type BodySender = mpsc::Sender<std::io::Result<Bytes>>;
+
+// A response body. Only Bytes is held in memory; files and streams are
+// written to the socket piece by piece.
+pub enum Body {
+    Bytes(Vec<u8>),
+    File { file: File, offset: u64, len: u64 },
+    #[allow(dead_code)]
+    Stream(mpsc::Receiver<std::io::Result<Bytes>>),
+}
+
+impl Body {
+    pub fn empty() -> Body {
+        Body::Bytes(vec![])
+    }
+
+    // A body fed chunk by chunk through the returned sender. It ends when the sender is dropped.
+    #[allow(dead_code)]
+    pub fn channel(capacity: usize) -> (BodySender, Body) {
+        let (tx, rx) = mpsc::channel(capacity);
+        (tx, Body::Stream(rx))
+    }
+
+    // Length in bytes, when known up front. Unknown lengths are sent chunked.
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::File { len, .. } => Some(*len),
+            Body::Stream(_) => None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    #[allow(dead_code)]
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    // Reads the whole body into memory, without any transfer framing
+    #[allow(dead_code)]
+    pub async fn collect(self) -> std::io::Result<Vec<u8>> {
+        match self {
+            Body::Stream(mut rx) => {
+                let mut out = vec![];
+                while let Some(chunk) = rx.recv().await {
+                    out.extend_from_slice(&chunk?);
+                }
+                Ok(out)
+            }
+            body => {
+                let mut out = vec![];
+                body.write_to(&mut out).await?;
+                Ok(out)
+            }
+        }
+    }
+
+    // Writes the body as it goes out on the wire: stream bodies use chunked encoding.
+    pub async fn write_to<W: AsyncWrite + Unpin>(self, writer: &mut W) -> std::io::Result<()> {
+        self.write_framed(writer, true).await
+    }
+
+    // Like write_to, but a stream goes out as is, for HTTP/1.0 clients that can't
+    // take chunked encoding. The connection has to be closed afterwards to end it.
+    pub async fn write_unchunked<W: AsyncWrite + Unpin>(self, writer: &mut W) -> std::io::Result<()> {
+        self.write_framed(writer, false).await
+    }
+
+    async fn write_framed<W: AsyncWrite + Unpin>(self, writer: &mut W, chunked: bool) -> std::io::Result<()> {
+        match self {
+            Body::Bytes(bytes) => writer.write_all(&bytes).await,
+            Body::File { mut file, offset, len } => {
+                file.seek(SeekFrom::Start(offset)).await?;
+                let copied = tokio::io::copy(&mut file.take(len), writer).await?;
+                if copied < len {
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "file shorter than its declared length"));
+                }
+                Ok(())
+            }
+            Body::Stream(mut rx) => {
+                while let Some(chunk) = rx.recv().await {
+                    let chunk = chunk?;
+                    if !chunked {
+                        writer.write_all(&chunk).await?;
+                        continue;
+                    }
+                    // An empty chunk would read as the end of the body
+                    if chunk.is_empty() {
+                        continue;
+                    }
+                    writer.write_all(format!("{:X}\r\n", chunk.len()).as_bytes()).await?;
+                    writer.write_all(&chunk).await?;
+                    writer.write_all(b"\r\n").await?;
+                }
+                if !chunked {
+                    return Ok(());
+                }
+                writer.write_all(b"0\r\n\r\n").await
+            }
+        }
+    }
+}
+
+impl Default for Body {
+    fn default() -> Body {
+        Body::empty()
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Body {
+        Body::Bytes(bytes)
+    }
+}
+
+impl std::fmt::Debug for Body {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Body::Bytes(bytes) => f.debug_tuple("Bytes").field(&bytes.len()).finish(),
+            Body::File { offset, len, .. } => f.debug_struct("File").field("offset", offset).field("len", len).finish(),
+            Body::Stream(_) => f.write_str("Stream"),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path ="tests/body.rs"]
+mod body_tests;
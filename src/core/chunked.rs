@@ -0,0 +1,193 @@
+use bytes::{Buf, BytesMut};
+
+use crate::core::parser::{is_tchar, parse_header_line, ParseError};
+
+#[derive(Debug, PartialEq)]
+enum ChunkState {
+    Size,
+    Data(usize),
+    DataEnd,
+    Trailers,
+    Done,
+}
+
+// Longest chunk-size line, extensions included, we'll wait for the end of
+const MAX_SIZE_LINE: usize = 4096;
+
+// Decodes a `Transfer-Encoding: chunked` body. Bytes are consumed from the
+// buffer as they're decoded, so it can be fed again after every socket read.
+#[derive(Debug)]
+pub struct ChunkedDecoder {
+    state: ChunkState,
+    body: Vec<u8>,
+    trailers: Vec<(String, String)>,
+    max_body: usize,
+    // Trailer lines count against this, like the header section does
+    max_trailer_bytes: usize,
+    trailer_bytes: usize,
+}
+
+impl ChunkedDecoder {
+    #[allow(dead_code)]
+    pub fn new() -> ChunkedDecoder {
+        ChunkedDecoder::with_limits(usize::MAX, usize::MAX)
+    }
+
+    pub fn with_limits(max_body: usize, max_trailer_bytes: usize) -> ChunkedDecoder {
+        ChunkedDecoder {
+            state: ChunkState::Size,
+            body: vec![],
+            trailers: vec![],
+            max_body,
+            max_trailer_bytes,
+            trailer_bytes: 0,
+        }
+    }
+
+    // Returns Ok(true) once the last chunk and any trailers have been read,
+    // Ok(false) when more bytes are needed.
+    pub fn decode(&mut self, buffer: &mut BytesMut) -> Result<bool, ParseError> {
+        loop {
+            match self.state {
+                ChunkState::Size => {
+                    let Some(line) = take_line(buffer) else {
+                        // Checked before the CRLF arrives, so an endless line can't grow the buffer forever
+                        if buffer.len() > MAX_SIZE_LINE {
+                            return Err(ParseError::InvalidChunk);
+                        }
+                        return Ok(false);
+                    };
+                    let size = parse_chunk_size(&line)?;
+                    // Rejected on the size line, before any of the chunk is buffered
+                    if size > self.max_body - self.body.len() {
+                        return Err(ParseError::BodyTooLarge);
+                    }
+                    self.state = if size == 0 { ChunkState::Trailers } else { ChunkState::Data(size) };
+                }
+                ChunkState::Data(remaining) => {
+                    if buffer.is_empty() {
+                        return Ok(false);
+                    }
+                    let n = remaining.min(buffer.len());
+                    self.body.extend_from_slice(&buffer[..n]);
+                    buffer.advance(n);
+                    self.state = if n == remaining { ChunkState::DataEnd } else { ChunkState::Data(remaining - n) };
+                }
+                ChunkState::DataEnd => {
+                    if buffer.len() < 2 {
+                        return Ok(false);
+                    }
+                    if &buffer[..2] != b"\r\n" {
+                        return Err(ParseError::InvalidChunk);
+                    }
+                    buffer.advance(2);
+                    self.state = ChunkState::Size;
+                }
+                ChunkState::Trailers => {
+                    let Some(line) = take_line(buffer) else {
+                        if buffer.len() > self.max_trailer_bytes.saturating_sub(self.trailer_bytes) {
+                            return Err(ParseError::HeadersTooLarge);
+                        }
+                        return Ok(false);
+                    };
+                    if line.is_empty() {
+                        self.state = ChunkState::Done;
+                        continue;
+                    }
+                    self.trailer_bytes += line.len() + 2;
+                    if self.trailer_bytes > self.max_trailer_bytes {
+                        return Err(ParseError::HeadersTooLarge);
+                    }
+                    let text = String::from_utf8(line).map_err(|_| ParseError::InvalidHeader)?;
+                    let (name, value) = parse_header_line(&text)?;
+                    self.trailers.push((name.to_string(), value.to_string()));
+                }
+                ChunkState::Done => return Ok(true),
+            }
+        }
+    }
+
+    pub fn into_parts(self) -> (Vec<u8>, Vec<(String, String)>) {
+        (self.body, self.trailers)
+    }
+}
+
+// Removes one CRLF terminated line from the front of the buffer, without the CRLF.
+fn take_line(buffer: &mut BytesMut) -> Option<Vec<u8>> {
+    let end = buffer.windows(2).position(|w| w == b"\r\n")?;
+    let line = buffer.split_to(end).to_vec();
+    buffer.advance(2);
+    Some(line)
+}
+
+fn parse_chunk_size(line: &[u8]) -> Result<usize, ParseError> {
+    let line = std::str::from_utf8(line).map_err(|_| ParseError::InvalidChunk)?;
+    let (size, extensions) = match line.split_once(';') {
+        Some((size, extensions)) => (size, Some(extensions)),
+        None => (line, None),
+    };
+    let size = size.trim_end_matches(WHITESPACE);
+    // from_str_radix would accept a leading "+"
+    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ParseError::InvalidChunk);
+    }
+    // Extensions carry nothing we use, but they're still checked so a stray byte
+    // (a bare LF, say) can't make us frame the body differently from a proxy
+    if let Some(extensions) = extensions {
+        check_extensions(extensions)?;
+    }
+    usize::from_str_radix(size, 16).map_err(|_| ParseError::InvalidChunk)
+}
+
+const WHITESPACE: [char; 2] = [' ', '\t'];
+
+// Everything after the first ";": `name[=value]` pairs split by ";", where a
+// value is a token or a quoted string (RFC 9112 section 7.1.1)
+fn check_extensions(mut rest: &str) -> Result<(), ParseError> {
+    loop {
+        rest = rest.trim_start_matches(WHITESPACE);
+        rest = skip_token(rest)?.trim_start_matches(WHITESPACE);
+        if let Some(value) = rest.strip_prefix('=') {
+            let value = value.trim_start_matches(WHITESPACE);
+            rest = match value.strip_prefix('"') {
+                Some(quoted) => skip_quoted(quoted)?,
+                None => skip_token(value)?,
+            };
+            rest = rest.trim_start_matches(WHITESPACE);
+        }
+        match rest.strip_prefix(';') {
+            Some(next) => rest = next,
+            None if rest.is_empty() => return Ok(()),
+            None => return Err(ParseError::InvalidChunk),
+        }
+    }
+}
+
+// What's left after a leading token, which mustn't be empty
+fn skip_token(s: &str) -> Result<&str, ParseError> {
+    let end = s.bytes().position(|b| !is_tchar(b)).unwrap_or(s.len());
+    if end == 0 {
+        return Err(ParseError::InvalidChunk);
+    }
+    Ok(&s[end..])
+}
+
+// What's left after a quoted string, its opening quote already taken off
+fn skip_quoted(s: &str) -> Result<&str, ParseError> {
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        let c = match c {
+            '"' => return Ok(&s[i + 1..]),
+            '\\' => chars.next().ok_or(ParseError::InvalidChunk)?.1,
+            c => c,
+        };
+        if c.is_ascii_control() && c != '\t' {
+            return Err(ParseError::InvalidChunk);
+        }
+    }
+    Err(ParseError::InvalidChunk)
+}
+
+#[cfg(test)]
+#[path ="tests/chunked.rs"]
+mod chunked_tests;
@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct ConnectionConfig {
+    pub keep_alive_timeout: Duration,
+    pub max_requests: usize,
+    // Deadlines for each phase of a request, so a client trickling bytes can't hold
+    // a worker. Head and body each have one deadline for the whole phase, not per read.
+    pub handshake_timeout: Duration,
+    pub head_timeout: Duration,
+    pub body_timeout: Duration,
+    pub write_timeout: Duration,
+    pub limits: Limits,
+    // How long in-flight requests get to finish once shutdown starts
+    pub shutdown_timeout: Duration,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> ConnectionConfig {
+        ConnectionConfig {
+            keep_alive_timeout: Duration::from_secs(5),
+            max_requests: 100,
+            handshake_timeout: Duration::from_secs(10),
+            head_timeout: Duration::from_secs(10),
+            body_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(30),
+            limits: Limits::default(),
+            shutdown_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ConnectionConfig {
+    // Anything missing or unparsable in the environment falls back to the default.
+    pub fn from_env() -> ConnectionConfig {
+        let default = ConnectionConfig::default();
+        ConnectionConfig {
+            keep_alive_timeout: Duration::from_secs(env_or("KEEP_ALIVE_TIMEOUT", default.keep_alive_timeout.as_secs())),
+            max_requests: env_or("KEEP_ALIVE_MAX", default.max_requests),
+            handshake_timeout: Duration::from_secs(env_or("HANDSHAKE_TIMEOUT", default.handshake_timeout.as_secs())),
+            head_timeout: Duration::from_secs(env_or("HEAD_TIMEOUT", default.head_timeout.as_secs())),
+            body_timeout: Duration::from_secs(env_or("BODY_TIMEOUT", default.body_timeout.as_secs())),
+            write_timeout: Duration::from_secs(env_or("WRITE_TIMEOUT", default.write_timeout.as_secs())),
+            limits: Limits::from_env(),
+            shutdown_timeout: Duration::from_secs(env_or("SHUTDOWN_TIMEOUT", default.shutdown_timeout.as_secs())),
+        }
+    }
+}
+
+// Size limits on incoming requests. Going over one is answered with
+// 414 (URI), 431 (header bytes or count) or 413 (body).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    pub max_uri_length: usize,
+    // Everything after the request line, up to and including the blank line
+    pub max_header_bytes: usize,
+    pub max_headers: usize,
+    pub max_body_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_uri_length: 8 * 1024,
+            max_header_bytes: 16 * 1024,
+            max_headers: 100,
+            max_body_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+impl Limits {
+    pub fn from_env() -> Limits {
+        let default = Limits::default();
+        Limits {
+            max_uri_length: env_or("MAX_URI_LENGTH", default.max_uri_length),
+            max_header_bytes: env_or("MAX_HEADER_BYTES", default.max_header_bytes),
+            max_headers: env_or("MAX_HEADERS", default.max_headers),
+            max_body_size: env_or("MAX_BODY_SIZE", default.max_body_size),
+        }
+    }
+}
+
+// Whether a listener speaks TLS or cleartext HTTP, e.g. behind a TLS-terminating load balancer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Tls,
+    Plain,
+}
+
+impl Protocol {
+    // PROTOCOL=plain turns TLS off; anything else keeps it on
+    pub fn from_env() -> Protocol {
+        env_or("PROTOCOL", Protocol::Tls)
+    }
+}
+
+impl std::str::FromStr for Protocol {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Protocol, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "tls" | "https" => Ok(Protocol::Tls),
+            "plain" | "http" => Ok(Protocol::Plain),
+            _ => Err(()),
+        }
+    }
+}
+
+// What a listener serves: the full app, or only redirects to the HTTPS listener
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Service {
+    App,
+    Redirect,
+}
+
+impl std::str::FromStr for Service {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Service, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "app" => Ok(Service::App),
+            "redirect" => Ok(Service::Redirect),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListenerConfig {
+    pub address: String,
+    pub protocol: Protocol,
+    pub service: Service,
+}
+
+impl ListenerConfig {
+    // LISTENERS is a comma-separated list of `address [protocol] [service]`, e.g.
+    // "0.0.0.0:443 tls app, 0.0.0.0:80 plain redirect". Protocol defaults to tls and
+    // service to app. Without LISTENERS, BIND_ADDRESS and PROTOCOL give a single app listener.
+    pub fn from_env() -> Vec<ListenerConfig> {
+        if let Ok(value) = std::env::var("LISTENERS") {
+            return parse_listeners(&value).expect("LISTENERS is invalid");
+        }
+
+        let address = std::env::var("BIND_ADDRESS").expect("BIND_ADDRESS not set");
+        vec![ListenerConfig { address, protocol: Protocol::from_env(), service: Service::App }]
+    }
+}
+
+pub fn parse_listeners(value: &str) -> Option<Vec<ListenerConfig>> {
+    value.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let mut parts = entry.split_whitespace();
+            let address = parts.next()?.to_string();
+            let protocol = parts.next().map_or(Ok(Protocol::Tls), str::parse).ok()?;
+            let service = parts.next().map_or(Ok(Service::App), str::parse).ok()?;
+            if parts.next().is_some() {
+                return None;
+            }
+            Some(ListenerConfig { address, protocol, service })
+        })
+        .collect()
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+#[path ="tests/config.rs"]
+mod config_tests;
@@ -2,104 +2,207 @@ use std::sync::Arc;
 
 use bytes::BytesMut;
 
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tokio_rustls::server::TlsStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::watch;
+use tokio::time::Instant;
 
 use crate::core::{
-    parser::{generate_body, generate_headers, parse_request},
+    body::Body,
+    config::ConnectionConfig,
+    connection_info::ConnectionInfo,
+    parser::{Method, ParseError, ParseStatus, Request, RequestParser},
     response::{Response, StatusCode},
-    router::router,
 };
 use crate::http::middleware::Dispatcher;
 
-use super::parser::generate_cookies;
-
-pub async fn handle_client(mut socket: TlsStream<TcpStream>, dispatcher: Arc<Dispatcher>) {
+// Serves one connection, TLS or cleartext. The caller builds `info` from the
+// stream before handing it over (ConnectionInfo::from_tls / from_tcp).
+//
+// Once `shutdown` flips to true, an idle keep-alive connection is closed straight
+// away, and a busy one is closed after the response it's working on.
+pub async fn handle_client<S>(
+    mut socket: S,
+    info: ConnectionInfo,
+    dispatcher: Arc<Dispatcher>,
+    config: Arc<ConnectionConfig>,
+    mut shutdown: watch::Receiver<bool>,
+)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    let info = Arc::new(info);
+    // Lives across requests: whatever follows a request in the same read
+    // (a pipelined request, or the next keep-alive one) is parsed next time round.
+    let mut master_buffer = BytesMut::new();
+    let mut served = 0;
 
     loop {
-        let mut master_buffer = BytesMut::new();
-
-        let early_return = collect_socket(&mut socket, &mut master_buffer).await;
-
-        if early_return {
-            break;
+        // Only the wait for the next request counts as idle, not a slow upload.
+        // A new connection still gets its first request served during shutdown.
+        if master_buffer.is_empty() {
+            let read = tokio::time::timeout(config.keep_alive_timeout, socket.read_buf(&mut master_buffer));
+            let read = if served == 0 {
+                read.await
+            } else {
+                tokio::select! {
+                    read = read => read,
+                    _ = shutdown_started(&mut shutdown) => break,
+                }
+            };
+            match read {
+                Ok(Ok(n)) if n > 0 => {}
+                _ => break,
+            }
         }
 
-        let parse_result = parse_request(&master_buffer);
-        let (mut idx, mut req) = if parse_result.is_ok() {
-            parse_result.unwrap()
+        let mut req = match collect_socket(&mut socket, &mut master_buffer, &config).await {
+            Collected::Request(req) => req,
+            Collected::Invalid(e) => {
+                let status = e.status();
+                let text = format!("{} {}", status.as_u16(), status.reason());
+                send_response(&mut socket, Response::new()
+                    .status(status)
+                    .header("Connection", "close")
+                    .text(&text), false, "HTTP/1.1", &config).await;
+                break;
+            }
+            Collected::TimedOut => {
+                send_response(&mut socket, Response::new()
+                    .status(StatusCode::RequestTimeout)
+                    .header("Connection", "close")
+                    .text(&"408 Request Timeout"), false, "HTTP/1.1", &config).await;
+                break;
+            }
+            Collected::Closed => break,
+        };
+
+        req.connection = Arc::clone(&info);
+        served += 1;
+        let keep_alive = wants_keep_alive(&req) && served < config.max_requests;
+        let is_head = req.method == Method::HEAD;
+        let version = req.version.clone();
+
+        let mut response = dispatcher.dispatch(req).await;
+        // An HTTP/1.0 client can only find the end of a stream body by the connection closing
+        let close_delimited = version == "HTTP/1.0" && response.body.len().is_none();
+        let keep_alive = keep_alive && !close_delimited && !*shutdown.borrow();
+
+        response = if keep_alive {
+            let timeout = config.keep_alive_timeout.as_secs();
+            let max = config.max_requests - served;
+            response.header("Connection", "keep-alive")
+                .header("Keep-Alive", format!("timeout={}, max={}", timeout, max).as_str())
         } else {
-            send_response(&mut socket, Response::new()
-                .status(StatusCode::BadRequest)
-                .finalize().to_vec()).await;
-            break;
+            response.header("Connection", "close")
         };
 
-        if req.headers.get("Connection")
-            .map(|v| v.eq_ignore_ascii_case("close"))
-                .unwrap_or(false) {
+        if !send_response(&mut socket, response, is_head, &version, &config).await || !keep_alive {
             break;
         }
+    }
 
-        req.headers = generate_headers(&mut master_buffer, &mut idx);
-        req.cookies = Some(generate_cookies(&req));
-        req.body = generate_body(req.headers.get("Content-Length"), &mut master_buffer, idx);
+    // Closing TLS flushes whatever ciphertext is still buffered, which a client
+    // that stopped reading would otherwise make us wait on forever
+    let _ = tokio::time::timeout(config.write_timeout, socket.shutdown()).await;
+}
 
-        let mut mw_response = dispatcher.dispatch(req.clone()).await;
+// Resolves once shutdown starts. If the sender is gone it never will.
+pub async fn shutdown_started(shutdown: &mut watch::Receiver<bool>) {
+    if shutdown.wait_for(|started| *started).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
 
-        let mut response = if mw_response.status == StatusCode::Unauthorized {
-            mw_response.body = Vec::from(b"401 Unauthorized");
-            mw_response
-        } else {
-            router(req, mw_response).await
-        };
+// HTTP/1.1 connections persist unless the client says otherwise, HTTP/1.0 ones only on request.
+fn wants_keep_alive(req: &Request) -> bool {
+    let connection = req.headers.get("Connection").unwrap_or("");
+    let has_token = |token: &str| connection.split(',').any(|t| t.trim().eq_ignore_ascii_case(token));
 
-        send_response(&mut socket, response.finalize().to_vec()).await;
+    if has_token("close") {
+        return false;
     }
+    has_token("keep-alive") || req.version == "HTTP/1.1"
+}
+
+#[allow(clippy::large_enum_variant)]
+enum Collected {
+    Request(Request),
+    Invalid(ParseError),
+    // The head or body deadline passed first
+    TimedOut,
+    // The client went away, or the read failed
+    Closed,
 }
 
-async fn collect_socket(socket: &mut TlsStream<TcpStream>, master_buffer: &mut BytesMut) -> bool {
+// Reads until the parser has a whole request, body included. The head deadline
+// starts now, since the caller has already seen the request's first bytes;
+// the body deadline starts once the head is done.
+async fn collect_socket<S: AsyncRead + Unpin>(socket: &mut S, master_buffer: &mut BytesMut, config: &ConnectionConfig) -> Collected {
+    let mut parser = RequestParser::with_limits(config.limits.clone());
+    let head_deadline = Instant::now() + config.head_timeout;
+    let mut body_deadline = None;
     loop {
-        if master_buffer.len() >= 8000 {
-            return false;
+        match parser.parse(master_buffer) {
+            ParseStatus::Complete(req) => return Collected::Request(req),
+            ParseStatus::Error(e) => return Collected::Invalid(e),
+            ParseStatus::Partial => {}
         }
-        let bytes_read = socket.read_buf(master_buffer).await;
-        match bytes_read {
-            Ok(n) => {
-                if n == 0 {
-                    return true;
-                }
-            },
-            Err(e) => {
+
+        let deadline = match parser.in_body() {
+            true => *body_deadline.get_or_insert_with(|| Instant::now() + config.body_timeout),
+            false => head_deadline,
+        };
+        match tokio::time::timeout_at(deadline, socket.read_buf(master_buffer)).await {
+            Err(_) => return Collected::TimedOut,
+            Ok(Ok(0)) => return Collected::Closed,
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => {
                 println!("Error reading socket data: {:?}", e);
+                return Collected::Closed;
             }
         }
-        for window in master_buffer.windows(4) {
-            if window == [13, 10, 13, 10] {
-                return false;
-            }
+    }
+}
+
+// Returns false if the response couldn't be written in time and the connection should be dropped.
+async fn send_response<S: AsyncWrite + Unpin>(socket: &mut S, response: Response, head_only: bool, version: &str, config: &ConnectionConfig) -> bool {
+    match tokio::time::timeout(config.write_timeout, write_response(socket, response, head_only, version)).await {
+        Ok(sent) => sent,
+        Err(_) => {
+            eprintln!("Timed out sending response");
+            false
         }
     }
 }
 
-async fn send_response(socket: &mut TlsStream<TcpStream>, res_bytes: Vec<u8>) {
-    let result = socket.write(&res_bytes).await;
+// `version` is the client's, which decides whether a stream body can be chunked
+async fn write_response<S: AsyncWrite + Unpin>(socket: &mut S, mut response: Response, head_only: bool, version: &str) -> bool {
+    let mut res_bytes = response.finalize_head_for(version);
+    let result = match std::mem::take(&mut response.body) {
+        _ if head_only || response.status.is_bodiless() => socket.write_all(&res_bytes).await,
+        // Small in-memory bodies go out in the same write as the head
+        Body::Bytes(bytes) => {
+            res_bytes.extend_from_slice(&bytes);
+            socket.write_all(&res_bytes).await
+        }
+        body => match socket.write_all(&res_bytes).await {
+            Ok(_) if version == "HTTP/1.0" => body.write_unchunked(socket).await,
+            Ok(_) => body.write_to(socket).await,
+            Err(e) => Err(e),
+        },
+    };
     match result {
         Ok(_) => {
             // No errors
         }
         Err(e) => {
-            if e.kind() == std::io::ErrorKind::BrokenPipe {
-                return;
-            } else {
+            if e.kind() != std::io::ErrorKind::BrokenPipe {
                 eprintln!("Error sending response: {:?}", e);
-                return;
             }
+            return false;
         }
     }
-    let _ = socket.flush();
-    let _ = socket.shutdown().await;
+    socket.flush().await.is_ok()
 }
 
 #[cfg(test)]
@@ -0,0 +1,58 @@
+use std::net::SocketAddr;
+
+use tokio::net::TcpStream;
+use tokio_rustls::{rustls::pki_types::CertificateDer, server::TlsStream};
+
+// What's known about the connection a request came in on. Shared by every
+// request on the connection through `Request::connection`.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionInfo {
+    pub peer_addr: Option<SocketAddr>,
+    #[allow(dead_code)]
+    pub local_addr: Option<SocketAddr>,
+    // None for cleartext connections
+    pub tls: Option<TlsInfo>,
+}
+
+// The negotiated TLS session
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct TlsInfo {
+    // SNI sent by the client
+    pub server_name: Option<String>,
+    pub alpn_protocol: Option<String>,
+    // e.g. "TLSv1_3"
+    pub version: Option<String>,
+    // e.g. "TLS13_AES_256_GCM_SHA384"
+    pub cipher_suite: Option<String>,
+    // The client's certificate chain, when client auth is configured
+    pub peer_certificates: Vec<CertificateDer<'static>>,
+}
+
+impl ConnectionInfo {
+    pub fn from_tls(stream: &TlsStream<TcpStream>) -> ConnectionInfo {
+        let (tcp, session) = stream.get_ref();
+        let addrs = ConnectionInfo::from_tcp(tcp);
+        let tls = TlsInfo {
+            server_name: session.server_name().map(str::to_string),
+            alpn_protocol: session.alpn_protocol().map(|p| String::from_utf8_lossy(p).into_owned()),
+            version: session.protocol_version().map(|v| format!("{:?}", v)),
+            cipher_suite: session.negotiated_cipher_suite().map(|s| format!("{:?}", s.suite())),
+            peer_certificates: session.peer_certificates().map(|certs| certs.iter().map(|c| c.clone().into_owned()).collect()).unwrap_or_default(),
+        };
+
+        ConnectionInfo { tls: Some(tls), ..addrs }
+    }
+
+    pub fn from_tcp(stream: &TcpStream) -> ConnectionInfo {
+        ConnectionInfo {
+            peer_addr: stream.peer_addr().ok(),
+            local_addr: stream.local_addr().ok(),
+            tls: None,
+        }
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self.tls.is_some()
+    }
+}
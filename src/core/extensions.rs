@@ -0,0 +1,76 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::Arc,
+};
+
+// A type map carried on each request: one value per type. App state registered at
+// startup is copied in before the middleware chain runs; middleware adds its own
+// per-request values (session, authenticated user, request id) for handlers to read.
+// Values are held behind an Arc, so cloning a request doesn't clone them.
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    pub fn new() -> Extensions {
+        Extensions { map: HashMap::new() }
+    }
+
+    // Replaces any value of the same type
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.insert_arc(Arc::new(value));
+    }
+
+    pub fn insert_arc<T: Send + Sync + 'static>(&mut self, value: Arc<T>) {
+        self.map.insert(TypeId::of::<T>(), value);
+    }
+
+    #[allow(dead_code)]
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_arc<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        Arc::clone(self.map.get(&TypeId::of::<T>())?).downcast().ok()
+    }
+
+    #[allow(dead_code)]
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    #[allow(dead_code)]
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<Arc<T>> {
+        self.map.remove(&TypeId::of::<T>())?.downcast().ok()
+    }
+
+    // Copies in every value from `other`, replacing ones of the same type
+    pub fn extend(&mut self, other: &Extensions) {
+        for (id, value) in other.map.iter() {
+            self.map.insert(*id, Arc::clone(value));
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl std::fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Extensions").field("len", &self.map.len()).finish()
+    }
+}
+
+#[cfg(test)]
+#[path ="tests/extensions.rs"]
+mod extensions_tests;
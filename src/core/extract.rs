@@ -0,0 +1,188 @@
+use std::{collections::HashMap, ops::Deref, sync::Arc};
+
+use serde::de::DeserializeOwned;
+
+use crate::core::{
+    headers::HeaderMap,
+    parser::Request,
+    response::{Response, StatusCode},
+};
+
+// Something a handler can take as an argument, pulled out of the request before
+// the handler runs. A failure is answered with the rejection instead.
+pub trait FromRequest: Sized {
+    fn from_request(req: &Request) -> Result<Self, Rejection>;
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Rejection {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl Rejection {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Rejection {
+        Rejection { status, message: message.into() }
+    }
+
+    pub fn into_response(self) -> Response {
+        Response::new().status(self.status).text(&self.message)
+    }
+}
+
+impl FromRequest for Request {
+    fn from_request(req: &Request) -> Result<Request, Rejection> {
+        Ok(req.clone())
+    }
+}
+
+// The query string, e.g. `Query<Search>` for `?q=rust&page=2`
+#[derive(Debug)]
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Query<T> {
+    fn from_request(req: &Request) -> Result<Query<T>, Rejection> {
+        from_pairs(&req.query)
+            .map(Query)
+            .map_err(|e| Rejection::new(StatusCode::BadRequest, format!("Invalid query string: {}", e)))
+    }
+}
+
+// Route params, e.g. `Path<UserPath>` for `/users/:id`
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Path<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Path<T> {
+    fn from_request(req: &Request) -> Result<Path<T>, Rejection> {
+        from_pairs(&req.params)
+            .map(Path)
+            .map_err(|e| Rejection::new(StatusCode::BadRequest, format!("Invalid path params: {}", e)))
+    }
+}
+
+// A JSON body. Needs an application/json (or +json) Content-Type; malformed JSON
+// is a 400, well-formed JSON of the wrong shape a 422.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Json<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+    fn from_request(req: &Request) -> Result<Json<T>, Rejection> {
+        let is_json = content_type(req).is_some_and(|ct| ct == "application/json" || ct.ends_with("+json"));
+        if !is_json {
+            return Err(Rejection::new(StatusCode::UnsupportedMediaType, "Expected Content-Type: application/json"));
+        }
+
+        serde_json::from_slice(&req.body).map(Json).map_err(|e| {
+            let status = if e.is_data() { StatusCode::UnprocessableContent } else { StatusCode::BadRequest };
+            Rejection::new(status, format!("Invalid JSON body: {}", e))
+        })
+    }
+}
+
+// An application/x-www-form-urlencoded body
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Form<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Form<T> {
+    fn from_request(req: &Request) -> Result<Form<T>, Rejection> {
+        if content_type(req).as_deref() != Some("application/x-www-form-urlencoded") {
+            return Err(Rejection::new(StatusCode::UnsupportedMediaType, "Expected Content-Type: application/x-www-form-urlencoded"));
+        }
+
+        serde_urlencoded::from_bytes(&req.body)
+            .map(Form)
+            .map_err(|e| Rejection::new(StatusCode::UnprocessableContent, format!("Invalid form body: {}", e)))
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Cookies(pub HashMap<String, String>);
+
+impl FromRequest for Cookies {
+    fn from_request(req: &Request) -> Result<Cookies, Rejection> {
+        Ok(Cookies(req.cookies.clone().unwrap_or_default()))
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Headers(pub HeaderMap);
+
+impl FromRequest for Headers {
+    fn from_request(req: &Request) -> Result<Headers, Rejection> {
+        Ok(Headers(req.headers.clone()))
+    }
+}
+
+// App state registered at startup with Dispatcher::add_state or Router::with_state.
+// Asking for a type that was never registered is a server bug, so it's a 500 rather than a 400.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct State<T>(pub Arc<T>);
+
+impl<T: Send + Sync + 'static> FromRequest for State<T> {
+    fn from_request(req: &Request) -> Result<State<T>, Rejection> {
+        req.extensions.get_arc::<T>()
+            .map(State)
+            .ok_or_else(|| Rejection::new(StatusCode::InternalError, "Handler state is not available"))
+    }
+}
+
+impl<T> Deref for State<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+// A value middleware attached to the request, e.g. `Extension<Session>`.
+// Take `Option<Extension<T>>` when the middleware might not have run.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Extension<T>(pub Arc<T>);
+
+impl<T: Send + Sync + 'static> FromRequest for Extension<T> {
+    fn from_request(req: &Request) -> Result<Extension<T>, Rejection> {
+        req.extensions.get_arc::<T>()
+            .map(Extension)
+            .ok_or_else(|| Rejection::new(StatusCode::InternalError, "Request extension is not available"))
+    }
+}
+
+impl<T> Deref for Extension<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+// Turns any rejection into None, for arguments the handler can do without
+impl<T: FromRequest> FromRequest for Option<T> {
+    fn from_request(req: &Request) -> Result<Option<T>, Rejection> {
+        Ok(T::from_request(req).ok())
+    }
+}
+
+// Content-Type without parameters, lowercased
+#[allow(dead_code)]
+fn content_type(req: &Request) -> Option<String> {
+    let value = req.headers.get("Content-Type")?;
+    Some(value.split(';').next().unwrap_or("").trim().to_ascii_lowercase())
+}
+
+// The parser (query) and router (params) have already percent-decoded these, so they're re-encoded for
+// serde_urlencoded, which takes care of turning "2" into a number and so on.
+fn from_pairs<T: DeserializeOwned>(pairs: &HashMap<String, String>) -> Result<T, serde_urlencoded::de::Error> {
+    let encoded = serde_urlencoded::to_string(pairs).map_err(|e| serde::de::Error::custom(e.to_string()))?;
+    serde_urlencoded::from_str(&encoded)
+}
+
+#[cfg(test)]
+#[path ="tests/extract.rs"]
+mod extract_tests;
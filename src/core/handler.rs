@@ -0,0 +1,46 @@
+use std::future::Future;
+
+use crate::core::{extract::FromRequest, parser::Request, response::Response};
+use crate::http::middleware::ResponseFuture;
+
+// An async function whose arguments are all extractors, e.g.
+// `async fn show(Path(p): Path<UserPath>, State(db): State<Db>) -> Response`.
+// `Args` is the tuple of argument types; it only exists to keep the impls apart.
+pub trait Handler<Args>: Send + Sync + 'static {
+    fn call(&self, req: Request) -> ResponseFuture;
+}
+
+macro_rules! impl_handler {
+    ($($arg:ident),*) => {
+        impl<F, Fut, $($arg,)*> Handler<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Response> + Send + 'static,
+            $($arg: FromRequest,)*
+        {
+            #[allow(non_snake_case, unused_variables)]
+            fn call(&self, req: Request) -> ResponseFuture {
+                $(
+                    let $arg = match $arg::from_request(&req) {
+                        Ok(value) => value,
+                        Err(rejection) => {
+                            let response = rejection.into_response();
+                            return Box::pin(async move { response });
+                        }
+                    };
+                )*
+                Box::pin(self($($arg),*))
+            }
+        }
+    };
+}
+
+impl_handler!();
+impl_handler!(T1);
+impl_handler!(T1, T2);
+impl_handler!(T1, T2, T3);
+impl_handler!(T1, T2, T3, T4);
+impl_handler!(T1, T2, T3, T4, T5);
+impl_handler!(T1, T2, T3, T4, T5, T6);
+impl_handler!(T1, T2, T3, T4, T5, T6, T7);
+impl_handler!(T1, T2, T3, T4, T5, T6, T7, T8);
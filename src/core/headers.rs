@@ -0,0 +1,100 @@
+// Header names compare case-insensitively and may repeat (e.g. Set-Cookie).
+// Entries keep the order they were added in, which is also the order they're written out.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> HeaderMap {
+        HeaderMap { entries: vec![] }
+    }
+
+    // First value for the name, if any
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries.iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    // Replaces every existing value for the name. The first one keeps its position.
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        let value = value.into();
+        let mut replaced = false;
+        self.entries.retain_mut(|(k, v)| {
+            if !k.eq_ignore_ascii_case(&name) {
+                return true;
+            }
+            if replaced {
+                return false;
+            }
+            replaced = true;
+            *v = value.clone();
+            true
+        });
+        if !replaced {
+            self.entries.push((name, value));
+        }
+    }
+
+    // Adds another value, keeping any that are already there
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.entries.push((name.into(), value.into()));
+    }
+
+    // Removes every value for the name, returning the first
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let first = self.get(name).map(|v| v.to_string());
+        self.entries.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+        first
+    }
+
+    // Keeps only the entries the closure returns true for
+    pub fn retain(&mut self, mut f: impl FnMut(&str, &str) -> bool) {
+        self.entries.retain(|(k, v)| f(k, v));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Extend<(String, String)> for HeaderMap {
+    fn extend<I: IntoIterator<Item = (String, String)>>(&mut self, iter: I) {
+        for (name, value) in iter {
+            self.append(name, value);
+        }
+    }
+}
+
+impl FromIterator<(String, String)> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+#[cfg(test)]
+#[path ="tests/headers.rs"]
+mod headers_tests;
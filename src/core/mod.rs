@@ -1,4 +1,12 @@
+pub mod body;
+pub mod chunked;
+pub mod config;
+pub mod extensions;
+pub mod extract;
+pub mod handler;
 pub mod connection;
+pub mod connection_info;
+pub mod headers;
 pub mod parser;
 pub mod response;
 pub mod router;
@@ -1,172 +1,477 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use bytes::BytesMut;
 
-#[derive(Debug, Clone)]
+use crate::core::{
+    chunked::ChunkedDecoder,
+    config::Limits,
+    connection_info::ConnectionInfo,
+    extensions::Extensions,
+    headers::HeaderMap,
+    response::StatusCode,
+};
+
+#[derive(Debug, Clone, Default)]
 pub struct Request {
     pub method: Method,
     pub path: String,
+    // The request target exactly as sent, query included and nothing decoded
+    pub target: String,
     pub version: String,
     pub query: HashMap<String, String>,
-    pub headers: HashMap<String, String>,
+    pub headers: HeaderMap,
     pub body: Vec<u8>,
+    // Fields sent after a chunked body. Kept apart from `headers`, since anything
+    // in front of us has already made its decisions on the header section.
+    pub trailers: HeaderMap,
     pub cookies: Option<HashMap<String, String>>,
+    // Filled in by the router from the matched route's `:name` and `*name` segments
+    pub params: HashMap<String, String>,
+    // App state and whatever middleware attached to this request, by type
+    pub extensions: Extensions,
+    // Peer and local address plus TLS details, set by the connection.
+    // Empty for requests built by hand.
+    pub connection: Arc<ConnectionInfo>,
+}
+
+impl Request {
+    #[allow(dead_code)]
+    pub fn param<T: std::str::FromStr>(&self, name: &str) -> Option<T> {
+        self.params.get(name)?.parse().ok()
+    }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub enum Method {
+    #[default]
     GET,
+    HEAD,
     POST,
     PUT,
+    PATCH,
     DELETE,
+    OPTIONS,
+    TRACE,
+    CONNECT,
+    Other(String),
 }
 
-#[derive(Debug)]
+impl Method {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::GET => "GET",
+            Method::HEAD => "HEAD",
+            Method::POST => "POST",
+            Method::PUT => "PUT",
+            Method::PATCH => "PATCH",
+            Method::DELETE => "DELETE",
+            Method::OPTIONS => "OPTIONS",
+            Method::TRACE => "TRACE",
+            Method::CONNECT => "CONNECT",
+            Method::Other(method) => method,
+        }
+    }
+}
+
+impl std::fmt::Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Method {
+    type Err = ParseError;
+
+    // Methods are case-sensitive; anything that isn't a known one but is
+    // still a valid token (e.g. WebDAV's PROPFIND) becomes Method::Other.
+    fn from_str(s: &str) -> Result<Method, ParseError> {
+        let method = match s {
+            "GET" => Method::GET,
+            "HEAD" => Method::HEAD,
+            "POST" => Method::POST,
+            "PUT" => Method::PUT,
+            "PATCH" => Method::PATCH,
+            "DELETE" => Method::DELETE,
+            "OPTIONS" => Method::OPTIONS,
+            "TRACE" => Method::TRACE,
+            "CONNECT" => Method::CONNECT,
+            other if is_token(other) => Method::Other(other.to_string()),
+            _ => return Err(ParseError::InvalidMethod),
+        };
+        Ok(method)
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub enum ParseError {
-    BadRequest,
+    InvalidRequestLine,
+    InvalidMethod,
+    // Bad percent-encoding in the target
+    InvalidTarget,
+    UnsupportedVersion,
+    InvalidHeader,
+    InvalidContentLength,
+    // Framing a proxy might read differently: Content-Length with Transfer-Encoding,
+    // disagreeing Content-Lengths, or a transfer coding we can't find the end of
+    AmbiguousLength,
+    // Missing on an HTTP/1.1 request, or sent more than once
+    InvalidHost,
+    // A transfer coding on top of chunked (gzip, say), which we can't undo
+    UnsupportedTransferCoding,
+    InvalidChunk,
+    UriTooLong,
+    HeadersTooLarge,
+    TooManyHeaders,
+    BodyTooLarge,
+}
+
+impl ParseError {
+    // The status the request is rejected with
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ParseError::UnsupportedVersion => StatusCode::HttpVersionNotSupported,
+            ParseError::UriTooLong => StatusCode::UriTooLong,
+            ParseError::HeadersTooLarge | ParseError::TooManyHeaders => StatusCode::RequestHeaderFieldsTooLarge,
+            ParseError::BodyTooLarge => StatusCode::ContentTooLarge,
+            ParseError::UnsupportedTransferCoding => StatusCode::NotImplemented,
+            _ => StatusCode::BadRequest,
+        }
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum ParseStatus {
+    Partial,
+    Complete(Request),
+    Error(ParseError),
+}
+
+#[derive(Debug)]
+enum ParserState {
+    Head,
+    Body { request: Request, length: usize },
+    Chunked { request: Request, decoder: ChunkedDecoder },
+}
+
+// Incremental request parser. Feed it the connection buffer after every read;
+// bytes belonging to the request are consumed, anything after it is left in place.
+#[derive(Debug)]
+pub struct RequestParser {
+    state: ParserState,
+    limits: Limits,
+}
+
+impl RequestParser {
+    #[allow(dead_code)]
+    pub fn new() -> RequestParser {
+        RequestParser::with_limits(Limits::default())
+    }
+
+    pub fn with_limits(limits: Limits) -> RequestParser {
+        RequestParser { state: ParserState::Head, limits }
+    }
+
+    // Whether the head has been parsed and the parser is now waiting on the body
+    pub fn in_body(&self) -> bool {
+        !matches!(self.state, ParserState::Head)
+    }
+
+    pub fn parse(&mut self, buffer: &mut BytesMut) -> ParseStatus {
+        loop {
+            match std::mem::replace(&mut self.state, ParserState::Head) {
+                ParserState::Head => {
+                    // Checked before the head is complete too, so an endless head can't grow the buffer forever
+                    let end = buffer.windows(4).position(|w| w == b"\r\n\r\n");
+                    let head_len = end.map_or(buffer.len(), |end| end + 4);
+                    if let Err(e) = check_head_size(&buffer[..head_len], &self.limits) {
+                        return ParseStatus::Error(e);
+                    }
+                    // Lines must end in CRLF; a head using bare LF would otherwise never complete
+                    if has_bare_lf(&buffer[..head_len]) {
+                        return ParseStatus::Error(ParseError::InvalidHeader);
+                    }
+                    let Some(end) = end else {
+                        return ParseStatus::Partial;
+                    };
+                    let mut head = buffer.split_to(end + 4);
+                    match parse_head(&mut head, &self.limits) {
+                        Ok(state) => self.state = state,
+                        Err(e) => return ParseStatus::Error(e),
+                    }
+                }
+                ParserState::Body { mut request, length } => {
+                    if buffer.len() < length {
+                        self.state = ParserState::Body { request, length };
+                        return ParseStatus::Partial;
+                    }
+                    request.body = buffer.split_to(length).to_vec();
+                    return ParseStatus::Complete(request);
+                }
+                ParserState::Chunked { mut request, mut decoder } => {
+                    match decoder.decode(buffer) {
+                        Ok(true) => {
+                            let (body, trailers) = decoder.into_parts();
+                            request.body = body;
+                            request.trailers.extend(trailers);
+                            return ParseStatus::Complete(request);
+                        }
+                        Ok(false) => {
+                            self.state = ParserState::Chunked { request, decoder };
+                            return ParseStatus::Partial;
+                        }
+                        Err(e) => return ParseStatus::Error(e),
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Longest method plus spaces and version we allow on top of the URI before
+// a request line with no end in sight is rejected
+const REQUEST_LINE_OVERHEAD: usize = 32;
+
+// `head` is what has arrived of the head so far, including the blank line if it has
+fn check_head_size(head: &[u8], limits: &Limits) -> Result<(), ParseError> {
+    let line_end = head.windows(2).position(|w| w == b"\r\n");
+    let line = &head[..line_end.unwrap_or(head.len())];
+
+    let target_len = line.split(|b| *b == b' ').nth(1).map_or(0, |t| t.len());
+    if target_len > limits.max_uri_length || line.len() > limits.max_uri_length + REQUEST_LINE_OVERHEAD {
+        return Err(ParseError::UriTooLong);
+    }
+    if let Some(line_end) = line_end {
+        if head.len() - (line_end + 2) > limits.max_header_bytes {
+            return Err(ParseError::HeadersTooLarge);
+        }
+    }
+    Ok(())
+}
+
+fn has_bare_lf(bytes: &[u8]) -> bool {
+    bytes.iter().enumerate().any(|(i, b)| *b == b'\n' && (i == 0 || bytes[i - 1] != b'\r'))
+}
+
+// Parses a complete head (request line through the blank line) and works out how the body is framed.
+fn parse_head(head: &mut BytesMut, limits: &Limits) -> Result<ParserState, ParseError> {
+    let (mut idx, mut request) = parse_request(head)?;
+    request.headers = generate_headers(head, &mut idx)?;
+    if request.headers.len() > limits.max_headers {
+        return Err(ParseError::TooManyHeaders);
+    }
+
+    // HTTP/1.1 requests carry exactly one Host (RFC 9112 section 3.2)
+    let hosts = request.headers.get_all("Host").count();
+    if hosts > 1 || (hosts == 0 && request.version == "HTTP/1.1") {
+        return Err(ParseError::InvalidHost);
+    }
+    request.cookies = Some(generate_cookies(&request));
+
+    // The proxy in front of us has to agree on where this request ends (RFC 9112 section 6.1)
+    if request.headers.contains_key("Transfer-Encoding") {
+        if request.headers.contains_key("Content-Length") || request.version == "HTTP/1.0" {
+            return Err(ParseError::AmbiguousLength);
+        }
+        check_transfer_codings(&request.headers)?;
+        let decoder = ChunkedDecoder::with_limits(limits.max_body_size, limits.max_header_bytes);
+        return Ok(ParserState::Chunked { request, decoder });
+    }
+
+    let length = content_length(&request.headers)?.unwrap_or(0);
+    if length > limits.max_body_size {
+        return Err(ParseError::BodyTooLarge);
+    }
+    Ok(ParserState::Body { request, length })
+}
+
+// Only a single `chunked` is decoded. Chunked has to come last and only once,
+// or the end of the body can't be found at all (RFC 9112 section 6.3).
+fn check_transfer_codings(headers: &HeaderMap) -> Result<(), ParseError> {
+    let codings: Vec<&str> = headers.get_all("Transfer-Encoding")
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+    let chunked = codings.iter().filter(|c| c.eq_ignore_ascii_case("chunked")).count();
+    if chunked != 1 || !codings.last().is_some_and(|c| c.eq_ignore_ascii_case("chunked")) {
+        return Err(ParseError::AmbiguousLength);
+    }
+    if codings.len() > 1 {
+        return Err(ParseError::UnsupportedTransferCoding);
+    }
+    Ok(())
+}
+
+// Repeated Content-Length values, as separate fields or a list, are only accepted when they all agree
+fn content_length(headers: &HeaderMap) -> Result<Option<usize>, ParseError> {
+    let mut length = None;
+    for value in headers.get_all("Content-Length").flat_map(|v| v.split(',')) {
+        let value = value.trim();
+        // `parse` alone would let "+5" through
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::InvalidContentLength);
+        }
+        let parsed = value.parse::<usize>().map_err(|_| ParseError::BodyTooLarge)?;
+        if length.is_some_and(|length| length != parsed) {
+            return Err(ParseError::AmbiguousLength);
+        }
+        length = Some(parsed);
+    }
+    Ok(length)
 }
 
 pub fn parse_request(bytes: &BytesMut) -> Result<(usize, Request), ParseError> {
     for i in 1..bytes.len() {
         if bytes[i - 1] == b"\r"[0] && bytes[i] == b"\n"[0] {
-            let req_chars: Vec<char> = bytes[..i].iter().map(|b| *b as char).collect();
+            // method SP target SP version, with nothing else in between
+            let line = &bytes[..i - 1];
+            if line.iter().any(|b| b.is_ascii_control()) {
+                return Err(ParseError::InvalidRequestLine);
+            }
+            let req_chars: Vec<char> = line.iter().map(|b| *b as char).collect();
             let req_string = req_chars[0..req_chars.len()].iter().collect::<String>();
             let req_vec: Vec<&str> = req_string.split(" ").collect();
 
-            if req_vec.len() < 3 {
-                return Err(ParseError::BadRequest);
+            if req_vec.len() != 3 {
+                return Err(ParseError::InvalidRequestLine);
             }
 
-            let method = match req_vec[0] {
-                "GET" => Method::GET,
-                "POST" => Method::POST,
-                "PUT" => Method::PUT,
-                "DELETE" => Method::DELETE,
-                _ => {
-                    println!("Error in request method: {}", req_vec[0]);
-                    return Err(ParseError::BadRequest);
-                }
-            };
+            let method: Method = req_vec[0].parse()?;
 
-            let mut path_query_split: Vec<String> = req_vec[1].split("?").map(|s| s.to_string()).collect();
-            let path = path_query_split[0].to_string();
-            if path_query_split.is_empty() || path.is_empty() {
-                return Err(ParseError::BadRequest);
+            let (path, raw_query) = match req_vec[1].split_once('?') {
+                Some((path, query)) => (path.to_string(), Some(query)),
+                None => (req_vec[1].to_string(), None),
+            };
+            if path.is_empty() {
+                return Err(ParseError::InvalidRequestLine);
             }
+            // The path stays encoded for routing, but has to be well formed
+            percent_decode_bytes(&path)?;
 
-            let version = req_vec[2].trim().to_string();
-            if !version.starts_with("HTTP/1.") {
-                return Err(ParseError::BadRequest);
+            let version = req_vec[2].to_string();
+            match version.strip_prefix("HTTP/").map(|v| v.as_bytes()) {
+                Some([b'1', b'.', minor]) if minor.is_ascii_digit() => {}
+                // A well-formed version we just don't speak, like HTTP/2.0
+                Some([major, b'.', minor]) if major.is_ascii_digit() && minor.is_ascii_digit() => {
+                    return Err(ParseError::UnsupportedVersion);
+                }
+                _ => return Err(ParseError::InvalidRequestLine),
             }
 
             let mut query_map: HashMap<String, String> = HashMap::new();
-            if path_query_split.len() > 1 {
-                for string in path_query_split.iter_mut() {
-                    let out = percent_decoder(string)?;
-                    *string = out;
-                }
-
-                for query in path_query_split[1].split("&") {
-                    let query_segments: Vec<&str> = query.split("=").collect();
-                    if query_segments.len() <= 1 {
-                        query_map.insert(query_segments[0].to_string(), "".to_string());
-                        continue;
-                    }
-                    query_map.insert(query_segments[0].to_string(), query_segments[1].to_string());
+            if let Some(raw_query) = raw_query {
+                // Split before decoding, so an encoded & or = stays part of the name or value
+                for pair in raw_query.split('&') {
+                    let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                    query_map.insert(decode_query_part(name)?, decode_query_part(value)?);
                 }
             }
 
             let request = Request {
                 method,
                 path,
+                target: req_vec[1].to_string(),
                 version,
                 query: query_map,
-                headers: HashMap::new(),
+                headers: HeaderMap::new(),
                 body: vec![],
+                trailers: HeaderMap::new(),
                 cookies: None,
+                params: HashMap::new(),
+                extensions: Extensions::new(),
+                connection: Arc::default(),
             };
 
             return Ok((i + 1, request))
         }
     }
-    return Err(ParseError::BadRequest);
+    Err(ParseError::InvalidRequestLine)
 }
 
-pub fn generate_headers(master_buffer: &mut BytesMut, idx: &mut usize) -> HashMap<String, String> {
+// A malformed line fails the whole request rather than being skipped, since
+// whatever forwarded it to us may have read it differently.
+pub fn generate_headers(master_buffer: &mut BytesMut, idx: &mut usize) -> Result<HeaderMap, ParseError> {
     let mut header_chars = vec![];
 
-    while !header_chars.ends_with(&['\r', '\n', '\r', '\n']) {
+    while *idx < master_buffer.len() {
         header_chars.push(master_buffer[*idx] as char);
         *idx += 1;
+        // A lone CRLF straight after the request line means there are no headers at all
+        if header_chars == ['\r', '\n'] || header_chars.ends_with(&['\r', '\n', '\r', '\n']) {
+            break;
+        }
     }
     let header_string = header_chars[0..header_chars.len()].iter().collect::<String>();
 
-    let mut header_map: HashMap<String, String> = HashMap::new();
-    for item in header_string.split("\r\n") {
-        let pair: Vec<&str> = item.split(": ").collect();
-        if pair.len() > 1 {
-            header_map.insert(pair[0].to_string(), pair[1].to_string());
-        }
+    let mut header_map = HeaderMap::new();
+    for line in header_string.split("\r\n").filter(|line| !line.is_empty()) {
+        let (name, value) = parse_header_line(line)?;
+        header_map.append(name, value);
     }
-    header_map
+    Ok(header_map)
 }
 
-pub fn generate_body(length: Option<&String>, master_buffer: &mut BytesMut, idx: usize) -> Vec<u8>{
-    match length {
-        Some(len) => {
-            let parsed = len.parse::<usize>();
-            let length = if parsed.is_ok() {
-                parsed.unwrap()
-            } else {
-                0
-            };
-            let max = master_buffer.len();
-            let end = if idx + length >= max { max } else { idx + length };
-            let received_content: Vec<u8> = master_buffer[idx..end].to_vec();
-            return received_content;
-        },
-        None => {
-            vec![]
-        }
+// Splits a `name: value` field line (RFC 9112 section 5). The name must be a token
+// directly followed by the colon, which also rules out obs-fold continuation lines
+// since they start with whitespace.
+pub fn parse_header_line(line: &str) -> Result<(&str, &str), ParseError> {
+    let (name, value) = line.split_once(':').ok_or(ParseError::InvalidHeader)?;
+    if !is_token(name) {
+        return Err(ParseError::InvalidHeader);
     }
+    let value = value.trim_matches([' ', '\t']);
+    if value.chars().any(|c| c.is_ascii_control() && c != '\t') {
+        return Err(ParseError::InvalidHeader);
+    }
+    Ok((name, value))
 }
 
-fn percent_decoder(input: &str) -> Result<String, ParseError> {
-    let mut iter = input.chars().peekable();
-    let mut out = String::new();
-
-    while let Some(ch) = iter.next() {
-        if ch != '%' {
-            out.push(ch);
-            continue;
-        }
-
-        let hi = iter.next().ok_or(ParseError::BadRequest)?;
-        let lo = iter.next().ok_or(ParseError::BadRequest)?;
-
-        let hi_val = hi.to_digit(16).ok_or(ParseError::BadRequest)?;
-        let lo_val = lo.to_digit(16).ok_or(ParseError::BadRequest)?;
+pub fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(is_tchar)
+}
 
-        let byte = (hi_val * 16 + lo_val) as u8;
+pub fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
 
-        if byte > 0x7F {
-            return Err(ParseError::BadRequest);
+// Undoes %XX escapes without assuming what the bytes are
+pub fn percent_decode_bytes(input: &str) -> Result<Vec<u8>, ParseError> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
         }
-
-        out.push(byte as char);
+        let hex_digit = |at: usize| bytes.get(at).and_then(|&b| (b as char).to_digit(16)).ok_or(ParseError::InvalidTarget);
+        out.push((hex_digit(i + 1)? * 16 + hex_digit(i + 2)?) as u8);
+        i += 3;
     }
     Ok(out)
 }
 
+// A name or value from an application/x-www-form-urlencoded query, where '+' is a space
+fn decode_query_part(input: &str) -> Result<String, ParseError> {
+    percent_decoder(&input.replace('+', " "))
+}
+
+// Decodes to UTF-8 text; escapes that don't form valid UTF-8 are an error
+pub fn percent_decoder(input: &str) -> Result<String, ParseError> {
+    String::from_utf8(percent_decode_bytes(input)?).map_err(|_| ParseError::InvalidTarget)
+}
+
 pub fn generate_cookies(req: &Request) -> HashMap<String, String>{
     let mut cookies = HashMap::new();
 
-    if let Some(cookies_string) = req.headers.get(&"Cookie".to_string()) {
+    for cookies_string in req.headers.get_all("Cookie") {
         cookies_string.split(";").for_each(|c| {
             let cookie = c.trim();
-            let pair: Vec<&str> = cookie.splitn(2, "=").collect();
-
-            cookies.insert(pair[0].trim().to_string(), pair[1].trim().to_string());
+            if let Some((name, value)) = cookie.split_once('=') {
+                cookies.insert(name.trim().to_string(), value.trim().to_string());
+            }
         });
     }
 
@@ -1,54 +1,195 @@
 use serde::Serialize;
 
-use std::collections::HashMap;
+use crate::core::{body::Body, headers::HeaderMap, parser::is_token};
 
 #[derive(Debug)]
 pub struct Response {
     pub status: StatusCode,
-    pub headers: HashMap<String, String>,
-    pub body: Vec<u8>,
+    pub headers: HeaderMap,
+    pub body: Body,
+    // Headers `header`/`append_header` refused to set, or that were dropped when serialising
+    pub header_errors: Vec<HeaderError>,
 }
 
-#[derive(Debug, PartialEq)]
-pub enum StatusCode {
-    Ok,                     // 200
-    BadRequest,             // 400
-    Unauthorized,           // 401
-    NotFound,               // 404
-    MethodNotAllowed,       // 405
-    InternalError,          // 500
+// Why a header was refused. A name must be a token; a value can't hold CR, LF
+// or other control characters, which would let it split the response.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderError {
+    InvalidName(String),
+    InvalidValue { name: String, value: String },
+}
+
+pub fn validate_header(key: &str, value: &str) -> Result<(), HeaderError> {
+    if !is_token(key) {
+        return Err(HeaderError::InvalidName(key.to_string()));
+    }
+    if !is_field_text(value) {
+        return Err(HeaderError::InvalidValue { name: key.to_string(), value: value.to_string() });
+    }
+    Ok(())
+}
+
+// Safe to put on a status or header line: no CR, LF or other control characters besides tab
+fn is_field_text(s: &str) -> bool {
+    !s.chars().any(|c| c.is_ascii_control() && c != '\t')
+}
+
+macro_rules! status_codes {
+    ($($variant:ident = $code:literal, $reason:literal;)*) => {
+        #[derive(Debug, PartialEq, Clone)]
+        pub enum StatusCode {
+            $($variant,)*
+            // Anything outside the registry, with its own reason phrase
+            Custom(u16, String),
+        }
+
+        impl StatusCode {
+            pub fn as_u16(&self) -> u16 {
+                match self {
+                    $(StatusCode::$variant => $code,)*
+                    StatusCode::Custom(code, _) => *code,
+                }
+            }
+
+            pub fn reason(&self) -> &str {
+                match self {
+                    $(StatusCode::$variant => $reason,)*
+                    StatusCode::Custom(_, reason) => reason,
+                }
+            }
+
+            // Custom codes need three digits and a reason that can't break the status line
+            pub fn is_valid(&self) -> bool {
+                match self {
+                    StatusCode::Custom(code, reason) => (100..=999).contains(code) && is_field_text(reason),
+                    _ => true,
+                }
+            }
+
+            // Registered codes only; use StatusCode::Custom for anything else
+            pub fn from_u16(code: u16) -> Option<StatusCode> {
+                match code {
+                    $($code => Some(StatusCode::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+// The IANA HTTP status code registry
+status_codes! {
+    Continue                        = 100, "Continue";
+    SwitchingProtocols              = 101, "Switching Protocols";
+    Processing                      = 102, "Processing";
+    EarlyHints                      = 103, "Early Hints";
+
+    Ok                              = 200, "OK";
+    Created                         = 201, "Created";
+    Accepted                        = 202, "Accepted";
+    NonAuthoritativeInformation     = 203, "Non-Authoritative Information";
+    NoContent                       = 204, "No Content";
+    ResetContent                    = 205, "Reset Content";
+    PartialContent                  = 206, "Partial Content";
+    MultiStatus                     = 207, "Multi-Status";
+    AlreadyReported                 = 208, "Already Reported";
+    ImUsed                          = 226, "IM Used";
+
+    MultipleChoices                 = 300, "Multiple Choices";
+    MovedPermanently                = 301, "Moved Permanently";
+    Found                           = 302, "Found";
+    SeeOther                        = 303, "See Other";
+    NotModified                     = 304, "Not Modified";
+    UseProxy                        = 305, "Use Proxy";
+    TemporaryRedirect               = 307, "Temporary Redirect";
+    PermanentRedirect               = 308, "Permanent Redirect";
+
+    BadRequest                      = 400, "Bad Request";
+    Unauthorized                    = 401, "Unauthorized";
+    PaymentRequired                 = 402, "Payment Required";
+    Forbidden                       = 403, "Forbidden";
+    NotFound                        = 404, "Not Found";
+    MethodNotAllowed                = 405, "Method Not Allowed";
+    NotAcceptable                   = 406, "Not Acceptable";
+    ProxyAuthenticationRequired     = 407, "Proxy Authentication Required";
+    RequestTimeout                  = 408, "Request Timeout";
+    Conflict                        = 409, "Conflict";
+    Gone                            = 410, "Gone";
+    LengthRequired                  = 411, "Length Required";
+    PreconditionFailed              = 412, "Precondition Failed";
+    ContentTooLarge                 = 413, "Content Too Large";
+    UriTooLong                      = 414, "URI Too Long";
+    UnsupportedMediaType            = 415, "Unsupported Media Type";
+    RangeNotSatisfiable             = 416, "Range Not Satisfiable";
+    ExpectationFailed               = 417, "Expectation Failed";
+    MisdirectedRequest              = 421, "Misdirected Request";
+    UnprocessableContent            = 422, "Unprocessable Content";
+    Locked                          = 423, "Locked";
+    FailedDependency                = 424, "Failed Dependency";
+    TooEarly                        = 425, "Too Early";
+    UpgradeRequired                 = 426, "Upgrade Required";
+    PreconditionRequired            = 428, "Precondition Required";
+    TooManyRequests                 = 429, "Too Many Requests";
+    RequestHeaderFieldsTooLarge     = 431, "Request Header Fields Too Large";
+    UnavailableForLegalReasons      = 451, "Unavailable For Legal Reasons";
+
+    InternalError                   = 500, "Internal Server Error";
+    NotImplemented                  = 501, "Not Implemented";
+    BadGateway                      = 502, "Bad Gateway";
+    ServiceUnavailable              = 503, "Service Unavailable";
+    GatewayTimeout                  = 504, "Gateway Timeout";
+    HttpVersionNotSupported         = 505, "HTTP Version Not Supported";
+    VariantAlsoNegotiates           = 506, "Variant Also Negotiates";
+    InsufficientStorage             = 507, "Insufficient Storage";
+    LoopDetected                    = 508, "Loop Detected";
+    NotExtended                     = 510, "Not Extended";
+    NetworkAuthenticationRequired   = 511, "Network Authentication Required";
+}
+
+impl StatusCode {
+    // 1xx, 204 and 304 responses never carry a body
+    pub fn is_bodiless(&self) -> bool {
+        let code = self.as_u16();
+        (100..200).contains(&code) || code == 204 || code == 304
+    }
+}
+
+impl From<&StatusCode> for u16 {
+    fn from(status: &StatusCode) -> u16 {
+        status.as_u16()
+    }
 }
 
 impl Response {
     pub fn new() -> Response {
-        Response { status: StatusCode::NotFound, headers: HashMap::new(), body: vec![] }
+        Response { status: StatusCode::NotFound, headers: HeaderMap::new(), body: Body::empty(), header_errors: vec![] }
     }
 
     pub fn not_found() -> Response {
-        Response { status: StatusCode::NotFound, headers: HashMap::new(), body: Vec::from(b"404 Not Found") }
+        Response { status: StatusCode::NotFound, headers: HeaderMap::new(), body: Body::from(b"404 Not Found".to_vec()), header_errors: vec![] }
     }
 
     pub fn text<T: AsRef<[u8]>>(mut self, body: &T) -> Response {
-        self.headers.insert("content-type".to_string(), "text/plain; charset=utf-8".to_string());
-        self.body = body.as_ref().to_vec();
+        self.headers.insert("content-type", "text/plain; charset=utf-8");
+        self.body = body.as_ref().to_vec().into();
         self
     }
 
     pub fn html(mut self, body: &str) -> Response {
-        self.headers.insert("content-type".to_string(), "text/html; charset=utf-8".to_string());
-        self.body = body.bytes().collect();
+        self.headers.insert("content-type", "text/html; charset=utf-8");
+        self.body = body.as_bytes().to_vec().into();
         self
     }
 
     pub fn json<T: Serialize>(mut self, json: &T) -> Response {
-        self.headers.insert("content-type".to_string(), "application/json".to_string());
+        self.headers.insert("content-type", "application/json");
         match serde_json::to_vec(json) {
             Ok(ok) => {
-                self.body = ok;
+                self.body = ok.into();
             },
             Err(_) => {
                 self = self.header("x-serialize-error", "true").status(StatusCode::InternalError);
-                self.body = b"{\"Error\": \"Could not serialize JSON\"}".to_vec()
+                self.body = b"{\"Error\": \"Could not serialize JSON\"}".to_vec().into()
             },
         }
         self
@@ -59,46 +200,118 @@ impl Response {
         self
     }
 
+    // Sets a header, replacing any existing values for it. An invalid header
+    // isn't set; it's recorded in `header_errors` instead.
     pub fn header(mut self, key: &str, value: &str) -> Response {
-        if key.contains(":") || key.contains("\r") || key.contains("\n") {
-            println!("Error: Invalid header entered");
-            return self
+        if let Err(e) = self.try_header(key, value) {
+            self.record_header_error(e);
         }
-        let lower = key.trim().to_lowercase();
-        self.headers.insert(lower.to_string(), value.to_string());
         self
     }
 
+    // Adds a header alongside any existing values, for headers like Set-Cookie that may repeat
+    pub fn append_header(mut self, key: &str, value: &str) -> Response {
+        if let Err(e) = self.try_append_header(key, value) {
+            self.record_header_error(e);
+        }
+        self
+    }
+
+    // Like `header`, for callers that want to handle the error themselves,
+    // e.g. when the value comes from the request
+    pub fn try_header(&mut self, key: &str, value: &str) -> Result<(), HeaderError> {
+        let key = key.trim();
+        validate_header(key, value)?;
+        self.headers.insert(key.to_lowercase(), value);
+        Ok(())
+    }
+
+    pub fn try_append_header(&mut self, key: &str, value: &str) -> Result<(), HeaderError> {
+        let key = key.trim();
+        validate_header(key, value)?;
+        self.headers.append(key.to_lowercase(), value);
+        Ok(())
+    }
+
+    fn record_header_error(&mut self, error: HeaderError) {
+        tracing::warn!(?error, "invalid response header dropped");
+        self.header_errors.push(error);
+    }
+
+    pub fn body(mut self, body: Body) -> Response {
+        self.body = body;
+        self
+    }
+
+    // Head plus body for in-memory bodies. File and stream bodies are left out;
+    // the connection writes those itself after finalize_head.
+    #[allow(dead_code)]
     pub fn finalize(&mut self) -> Vec<u8> {
-        self.headers.insert("content-length".to_string(), self.body.len().to_string());
-        let mut buffer = String::from("HTTP/1.1 ");
-        match &self.status {
-            StatusCode::Ok => {
-                buffer += "200 OK\r\n";
-            }
-            StatusCode::BadRequest => {
-                buffer += "400 Bad Request\r\n";
-            }
-            StatusCode::Unauthorized => {
-                buffer += "401 Unauthorized\r\n";
+        let mut bytes = self.finalize_head();
+        if self.status.is_bodiless() {
+            return bytes;
+        }
+        if let Some(body) = self.body.as_bytes() {
+            bytes.extend_from_slice(body);
+        }
+        bytes
+    }
+
+    // Status line and headers only. Content-Length still describes the body,
+    // which is what a HEAD response needs.
+    #[allow(dead_code)]
+    pub fn finalize_head(&mut self) -> Vec<u8> {
+        self.finalize_head_for("HTTP/1.1")
+    }
+
+    // As finalize_head, for a client speaking `version`. HTTP/1.0 clients can't take
+    // chunked encoding, so a body of unknown length gets no framing header at all
+    // and runs until the connection closes.
+    pub fn finalize_head_for(&mut self, version: &str) -> Vec<u8> {
+        if !self.status.is_valid() {
+            tracing::warn!(status = ?self.status, "invalid status replaced with 500");
+            self.status = StatusCode::InternalError;
+        }
+        // `headers` can be written to directly, skipping the checks in `header`,
+        // so anything that would break the head is dropped here instead
+        let mut errors = vec![];
+        self.headers.retain(|key, val| match validate_header(key, val) {
+            Ok(()) => true,
+            Err(e) => {
+                errors.push(e);
+                false
             }
-            StatusCode::NotFound => {
-                buffer += "404 Not Found\r\n";
+        });
+        for error in errors {
+            self.record_header_error(error);
+        }
+        match self.body.len() {
+            // A 304 may describe the resource's length, so a handler-set one is kept
+            _ if self.status.is_bodiless() => {
+                self.headers.remove("transfer-encoding");
+                if self.status != StatusCode::NotModified {
+                    self.headers.remove("content-length");
+                }
             }
-            StatusCode::MethodNotAllowed => {
-                buffer += "405 Method Not Allowed\r\n";
+            Some(len) => {
+                self.headers.remove("transfer-encoding");
+                self.headers.insert("content-length", len.to_string());
             }
-            StatusCode::InternalError => {
-                buffer += "500 Internal Error\r\n";
+            None => {
+                self.headers.remove("content-length");
+                if version == "HTTP/1.0" {
+                    self.headers.remove("transfer-encoding");
+                } else {
+                    self.headers.insert("transfer-encoding", "chunked");
+                }
             }
         }
+        let mut buffer = format!("HTTP/1.1 {} {}\r\n", self.status.as_u16(), self.status.reason());
         for (key, val) in self.headers.iter() {
             buffer += format!("{}: {}\r\n", key, val).as_str();
         }
         buffer += "\r\n";
-        let mut bytes = buffer.into_bytes();
-        bytes.extend_from_slice(&self.body);
-        bytes
+        buffer.into_bytes()
     }
 }
 
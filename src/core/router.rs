@@ -1,59 +1,257 @@
-use std::time::Duration;
-
-use serde_json::{json, Value};
+use std::{collections::{BTreeMap, HashMap}, sync::Arc, time::Duration};
 
 use crate::handlers::file_serving::serve_file;
+use crate::http::middleware::{compose, Middleware, Next};
 use crate::core::{
-    parser::{Request, Method},
+    extensions::Extensions,
+    extract::Query,
+    handler::Handler,
+    parser::{percent_decoder, Request, Method},
     response::{Response, StatusCode},
 };
 
-pub async fn router(req: Request, res: Response) -> Response {
-    let res = match (&req.method, req.path.as_str()) {
-        (Method::GET, "/")      => handle_root_get(&req, res).await,
-        (Method::POST, "/")     => handle_root_post(&req, res).await,
-        (Method::PUT, "/")      => handle_unallowed_method().await,
-        (Method::DELETE, "/")   => handle_unallowed_method().await,
+// A route pattern segment. Static segments beat params, which beat wildcards,
+// whatever order the routes were added in.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Static(String),
+    Param(String),
+    Wildcard(String),
+}
+
+impl Segment {
+    fn rank(&self) -> u8 {
+        match self {
+            Segment::Static(_) => 0,
+            Segment::Param(_) => 1,
+            Segment::Wildcard(_) => 2,
+        }
+    }
+}
 
-        (Method::GET, "/echo")  => handle_echo_get(&req, res).await,
-        (Method::POST, "/echo")  => handle_unallowed_method().await,
-        (Method::PUT, "/echo")  => handle_unallowed_method().await,
-        (Method::DELETE, "/echo")  => handle_unallowed_method().await,
+#[derive(Clone)]
+struct Route {
+    method: Method,
+    segments: Vec<Segment>,
+    handler: Next,
+}
 
-        (Method::GET, "/page")  => handle_page_get(&req, res).await,
+impl Route {
+    // Params captured from the path, or None if the path doesn't fit the pattern.
+    // Captured segments are percent-decoded after splitting, so an encoded "/"
+    // stays inside its segment; a segment with a bad escape or invalid UTF-8
+    // doesn't match.
+    fn matches(&self, path: &str) -> Option<HashMap<String, String>> {
+        let parts: Vec<&str> = split_path(path).collect();
+        let mut params = HashMap::new();
 
-        (Method::GET, "/sleep")  => handle_sleep().await,
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Static(s) => {
+                    if parts.get(i) != Some(&s.as_str()) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    let part = parts.get(i).filter(|p| !p.is_empty())?;
+                    params.insert(name.clone(), percent_decoder(part).ok()?);
+                }
+                Segment::Wildcard(name) => {
+                    let rest = parts.get(i..).unwrap_or_default().iter()
+                        .map(|part| percent_decoder(part))
+                        .collect::<Result<Vec<_>, _>>()
+                        .ok()?;
+                    params.insert(name.clone(), rest.join("/"));
+                    return Some(params);
+                }
+            }
+        }
 
-        (Method::GET, path) if path.starts_with("/static/") => handle_static(&req, res).await,
+        if parts.len() != self.segments.len() {
+            return None;
+        }
+        Some(params)
+    }
 
-        _                       => Response::not_found()
-    };
-    res
+    fn ranks(&self) -> Vec<u8> {
+        self.segments.iter().map(Segment::rank).collect()
+    }
 }
 
-async fn handle_root_get(_req: &Request, res: Response) -> Response {
-    res.status(StatusCode::Ok).text(&"Hello")
+// Maps method + path patterns to handlers. Patterns are made of static segments,
+// named params (`/users/:id`) and a trailing wildcard (`/static/*path`);
+// captured values end up in `Request::params`.
+//
+// Routers can be mounted inside one another with `nest`. Middleware added with
+// `layer` only wraps the router's own routes, nested ones included, and only runs
+// once a route has matched; 404s and 405s don't pass through it.
+#[derive(Clone, Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    state: Extensions,
+    middleware: Vec<Arc<dyn Middleware>>,
 }
 
-async fn handle_root_post(req: &Request, res: Response) -> Response {
-    res.status(StatusCode::Ok).text(&req.body)
-}
+impl Router {
+    pub fn new() -> Router {
+        Router { routes: vec![], state: Extensions::new(), middleware: vec![] }
+    }
+
+    // State for this router's handlers only, on top of what the Dispatcher provides.
+    // Can be called once per type.
+    #[allow(dead_code)]
+    pub fn with_state<T: Send + Sync + 'static>(mut self, state: T) -> Router {
+        self.state.insert(state);
+        self
+    }
+
+    // Handlers are async functions taking extractors (see core::extract), or just the Request
+    pub fn route<H, Args>(mut self, method: Method, pattern: &str, handler: H) -> Router
+    where
+        H: Handler<Args>,
+    {
+        let handler: Next = Arc::new(move |req| handler.call(req));
+        self.routes.push(Route { method, segments: parse_pattern(pattern), handler });
+        self
+    }
+
+    // Mounts every route of `router` under `prefix`, so `/users/:id` nested at
+    // `/api` answers `/api/users/:id`. The nested router keeps its own middleware
+    // and state, which apply to its routes only.
+    #[allow(dead_code)]
+    pub fn nest(mut self, prefix: &str, router: Router) -> Router {
+        let prefix = parse_pattern(prefix.trim_end_matches('/'));
+        let Router { routes, state, middleware } = router;
+
+        for route in routes {
+            let mut segments: Vec<Segment> = prefix.iter().filter(|s| **s != Segment::Static(String::new())).cloned().collect();
+            // The nested router's "/" is the prefix itself
+            if route.segments != [Segment::Static(String::new())] || segments.is_empty() {
+                segments.extend(route.segments);
+            }
+
+            let handler = compose(&middleware, route.handler);
+            let state = state.clone();
+            let handler: Next = Arc::new(move |mut req: Request| {
+                req.extensions.extend(&state);
+                handler(req)
+            });
+            self.routes.push(Route { method: route.method, segments, handler });
+        }
+        self
+    }
+
+    // Adds middleware around this router's routes. The first one added is the outermost.
+    #[allow(dead_code)]
+    pub fn layer(mut self, mw: impl Middleware + 'static) -> Router {
+        self.middleware.push(Arc::new(mw));
+        self
+    }
 
-async fn handle_echo_get(req: &Request, res: Response) -> Response {
-    let mut json = json!({});
-    for q in req.query.iter() {
-        json[q.0] = Value::String(q.1.to_string())
+    pub async fn handle(&self, mut req: Request) -> Response {
+        let mut found = self.find(&req.method, &req.path);
+        // Without a HEAD route of its own, HEAD is answered by the GET handler;
+        // the connection drops the body when writing
+        if found.is_none() && req.method == Method::HEAD {
+            found = self.find(&Method::GET, &req.path);
+        }
+
+        if let Some((route, params)) = found {
+            req.params = params;
+            req.extensions.extend(&self.state);
+            if self.middleware.is_empty() {
+                return (route.handler)(req).await;
+            }
+            return compose(&self.middleware, Arc::clone(&route.handler))(req).await;
+        }
+
+        let allowed = self.allowed_methods(&req.path);
+        if allowed.is_empty() {
+            return Response::not_found();
+        }
+
+        let allow = allowed.iter().map(|m| m.as_str()).collect::<Vec<_>>().join(", ");
+        // OPTIONS is answered here unless a route registered its own handler for it
+        if req.method == Method::OPTIONS {
+            return Response::new().status(StatusCode::Ok).header("Allow", &allow);
+        }
+        Response::new()
+            .status(StatusCode::MethodNotAllowed)
+            .header("Allow", &allow)
+            .text(&"405 Method Not Allowed")
+    }
+
+    // Every method the router answers for a path, used for Allow headers
+    pub fn allowed_methods(&self, path: &str) -> Vec<Method> {
+        let mut allowed: Vec<Method> = vec![];
+        for route in self.routes.iter().filter(|r| path == "*" || r.matches(path).is_some()) {
+            if !allowed.contains(&route.method) {
+                allowed.push(route.method.clone());
+            }
+        }
+        if let Some(pos) = allowed.iter().position(|m| m == &Method::GET) {
+            if !allowed.contains(&Method::HEAD) {
+                allowed.insert(pos + 1, Method::HEAD);
+            }
+        }
+        if !allowed.is_empty() && !allowed.contains(&Method::OPTIONS) {
+            allowed.push(Method::OPTIONS);
+        }
+        allowed
+    }
+
+    fn find(&self, method: &Method, path: &str) -> Option<(&Route, HashMap<String, String>)> {
+        self.routes.iter()
+            .filter(|r| &r.method == method)
+            .filter_map(|r| r.matches(path).map(|params| (r, params)))
+            .min_by_key(|(r, _)| r.ranks())
     }
-    res.status(StatusCode::Ok).json(&json)
 }
 
-async fn handle_page_get(_req: &Request, res: Response) -> Response {
-    let html = "<html></html>";
-    res.status(StatusCode::Ok).html(html)
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    split_path(pattern).map(|part| {
+        if let Some(name) = part.strip_prefix(':') {
+            Segment::Param(name.to_string())
+        } else if let Some(name) = part.strip_prefix('*') {
+            Segment::Wildcard(name.to_string())
+        } else {
+            Segment::Static(part.to_string())
+        }
+    }).collect()
+}
+
+// Only the one leading "/" is dropped, so "//admin" doesn't route as "/admin"
+// while path conditions (which see the raw path) treat it as something else
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.strip_prefix('/').unwrap_or(path).split('/')
 }
 
-async fn handle_unallowed_method() -> Response {
-    Response::new().status(StatusCode::MethodNotAllowed).text(&"405 Method Not Allowed")
+// The application's routes
+pub fn routes() -> Router {
+    Router::new()
+        .route(Method::GET, "/", handle_root_get)
+        .route(Method::POST, "/", handle_root_post)
+        .route(Method::GET, "/echo", handle_echo_get)
+        .route(Method::GET, "/page", handle_page_get)
+        .route(Method::GET, "/sleep", handle_sleep)
+        .route(Method::GET, "/static/*path", handle_static)
+}
+
+async fn handle_root_get() -> Response {
+    Response::new().status(StatusCode::Ok).text(&"Hello")
+}
+
+async fn handle_root_post(req: Request) -> Response {
+    Response::new().status(StatusCode::Ok).text(&req.body)
+}
+
+async fn handle_echo_get(Query(query): Query<BTreeMap<String, String>>) -> Response {
+    Response::new().status(StatusCode::Ok).json(&query)
+}
+
+async fn handle_page_get() -> Response {
+    let html = "<html></html>";
+    Response::new().status(StatusCode::Ok).html(html)
 }
 
 async fn handle_sleep() -> Response {
@@ -62,7 +260,10 @@ async fn handle_sleep() -> Response {
     Response::new().status(StatusCode::Ok).text(&"Slept for 5 seconds")
 }
 
-async fn handle_static(req: &Request, res: Response) -> Response {
-    let file_response = serve_file(&req, res).await;
-    file_response
+async fn handle_static(req: Request) -> Response {
+    serve_file(&req, Response::new()).await
 }
+
+#[cfg(test)]
+#[path ="tests/router.rs"]
+mod router_tests;
@@ -0,0 +1,65 @@
+use super::*;
+
+#[tokio::test]
+async fn bytes_body_is_written_as_is() {
+    let body = Body::from(b"hello".to_vec());
+    assert_eq!(body.len(), Some(5));
+
+    let mut out = vec![];
+    body.write_to(&mut out).await.unwrap();
+    assert_eq!(out, b"hello");
+}
+
+#[tokio::test]
+async fn file_body_writes_only_its_region() {
+    std::fs::create_dir_all("public").unwrap();
+    std::fs::write("public/body_region_test.txt", b"0123456789").unwrap();
+
+    let file = File::open("public/body_region_test.txt").await.unwrap();
+    let body = Body::File { file, offset: 2, len: 5 };
+    assert_eq!(body.len(), Some(5));
+
+    let mut out = vec![];
+    body.write_to(&mut out).await.unwrap();
+    assert_eq!(out, b"23456");
+
+    std::fs::remove_file("public/body_region_test.txt").unwrap();
+}
+
+#[tokio::test]
+async fn stream_body_is_chunk_encoded() {
+    let (tx, body) = Body::channel(4);
+    assert_eq!(body.len(), None);
+
+    tokio::spawn(async move {
+        tx.send(Ok(Bytes::from_static(b"hello "))).await.unwrap();
+        tx.send(Ok(Bytes::from_static(b""))).await.unwrap();
+        tx.send(Ok(Bytes::from_static(b"streaming world"))).await.unwrap();
+    });
+
+    let mut out = vec![];
+    body.write_to(&mut out).await.unwrap();
+    assert_eq!(out, b"6\r\nhello \r\nF\r\nstreaming world\r\n0\r\n\r\n");
+}
+
+#[tokio::test]
+async fn stream_error_aborts_the_write() {
+    let (tx, body) = Body::channel(1);
+    tokio::spawn(async move {
+        tx.send(Err(std::io::Error::other("disk gone"))).await.unwrap();
+    });
+
+    let mut out = vec![];
+    assert!(body.write_to(&mut out).await.is_err());
+}
+
+#[tokio::test]
+async fn collect_drops_transfer_framing() {
+    let (tx, body) = Body::channel(2);
+    tokio::spawn(async move {
+        tx.send(Ok(Bytes::from_static(b"ab"))).await.unwrap();
+        tx.send(Ok(Bytes::from_static(b"cd"))).await.unwrap();
+    });
+
+    assert_eq!(body.collect().await.unwrap(), b"abcd");
+}
@@ -0,0 +1,132 @@
+use super::*;
+
+#[test]
+fn decodes_single_read_body() {
+    let mut buf = BytesMut::from(&b"5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"[..]);
+    let mut decoder = ChunkedDecoder::new();
+
+    assert!(decoder.decode(&mut buf).unwrap());
+    let (body, trailers) = decoder.into_parts();
+    assert_eq!(body, b"hello world");
+    assert!(trailers.is_empty());
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn decodes_body_split_across_reads() {
+    let mut decoder = ChunkedDecoder::new();
+    let mut buf = BytesMut::new();
+
+    for piece in [&b"5\r\nhe"[..], b"llo\r", b"\n3\r\nabc", b"\r\n0\r\n"] {
+        buf.extend_from_slice(piece);
+        assert!(!decoder.decode(&mut buf).unwrap());
+    }
+
+    buf.extend_from_slice(b"\r\n");
+    assert!(decoder.decode(&mut buf).unwrap());
+    assert_eq!(decoder.into_parts().0, b"helloabc");
+}
+
+#[test]
+fn ignores_chunk_extensions() {
+    let mut buf = BytesMut::from(&b"4;name=value\r\ndata\r\n0;last\r\n\r\n"[..]);
+    let mut decoder = ChunkedDecoder::new();
+
+    assert!(decoder.decode(&mut buf).unwrap());
+    assert_eq!(decoder.into_parts().0, b"data");
+}
+
+#[test]
+fn collects_trailers() {
+    let mut buf = BytesMut::from(&b"1\r\na\r\n0\r\nX-Checksum: abc\r\nX-Other: 1\r\n\r\n"[..]);
+    let mut decoder = ChunkedDecoder::new();
+
+    assert!(decoder.decode(&mut buf).unwrap());
+    let (_, trailers) = decoder.into_parts();
+    assert_eq!(trailers, vec![
+        ("X-Checksum".to_string(), "abc".to_string()),
+        ("X-Other".to_string(), "1".to_string()),
+    ]);
+}
+
+#[test]
+fn leaves_bytes_after_final_chunk_in_buffer() {
+    let mut buf = BytesMut::from(&b"0\r\n\r\nGET / HTTP/1.1\r\n"[..]);
+    let mut decoder = ChunkedDecoder::new();
+
+    assert!(decoder.decode(&mut buf).unwrap());
+    assert_eq!(&buf[..], b"GET / HTTP/1.1\r\n");
+}
+
+#[test]
+fn errors_on_invalid_chunk_size() {
+    let mut buf = BytesMut::from(&b"zz\r\nhello\r\n"[..]);
+    let mut decoder = ChunkedDecoder::new();
+    assert!(decoder.decode(&mut buf).is_err());
+}
+
+#[test]
+fn errors_on_missing_chunk_terminator() {
+    let mut buf = BytesMut::from(&b"3\r\nabcXY0\r\n\r\n"[..]);
+    let mut decoder = ChunkedDecoder::new();
+    assert!(decoder.decode(&mut buf).is_err());
+}
+
+#[test]
+fn errors_when_body_exceeds_limit() {
+    let mut buf = BytesMut::from(&b"4\r\nabcd\r\n4\r\nefgh\r\n0\r\n\r\n"[..]);
+    let mut decoder = ChunkedDecoder::with_limits(6, usize::MAX);
+    assert_eq!(decoder.decode(&mut buf).unwrap_err(), ParseError::BodyTooLarge);
+}
+
+#[test]
+fn errors_when_trailers_exceed_limit() {
+    let mut buf = BytesMut::from(&b"0\r\nX-Trailer: a long trailer value\r\n\r\n"[..]);
+    let mut decoder = ChunkedDecoder::with_limits(usize::MAX, 16);
+    assert_eq!(decoder.decode(&mut buf).unwrap_err(), ParseError::HeadersTooLarge);
+}
+
+#[test]
+fn errors_on_signed_chunk_size() {
+    let mut buf = BytesMut::from(&b"+3\r\nabc\r\n0\r\n\r\n"[..]);
+    assert_eq!(ChunkedDecoder::new().decode(&mut buf).unwrap_err(), ParseError::InvalidChunk);
+}
+
+#[test]
+fn errors_on_endless_size_line() {
+    let mut buf = BytesMut::from(vec![b'1'; 5000].as_slice());
+    assert_eq!(ChunkedDecoder::new().decode(&mut buf).unwrap_err(), ParseError::InvalidChunk);
+}
+
+#[test]
+fn errors_on_unfinished_trailer_over_limit() {
+    let mut decoder = ChunkedDecoder::with_limits(usize::MAX, 16);
+    let mut buf = BytesMut::from(&b"0\r\nX-T: a"[..]);
+    assert!(!decoder.decode(&mut buf).unwrap());
+    buf.extend_from_slice(b" trailer that never ends");
+    assert_eq!(decoder.decode(&mut buf).unwrap_err(), ParseError::HeadersTooLarge);
+}
+
+#[test]
+fn accepts_well_formed_extensions() {
+    let mut buf = BytesMut::from(&b"3;a;b=1 ; c = \"x;\\\"y\"\r\nabc\r\n0;last\r\n\r\n"[..]);
+    let mut decoder = ChunkedDecoder::new();
+    assert!(decoder.decode(&mut buf).unwrap());
+    assert_eq!(decoder.into_parts().0, b"abc");
+}
+
+#[test]
+fn errors_on_malformed_extensions() {
+    let lines: [&[u8]; 6] = [
+        b"3;x\nGET /a HTTP/1.1\r\nabc\r\n0\r\n\r\n",
+        b"3;\r\nabc\r\n0\r\n\r\n",
+        b"3;a=\r\nabc\r\n0\r\n\r\n",
+        b"3;a=\"open\r\nabc\r\n0\r\n\r\n",
+        b"3;a b\r\nabc\r\n0\r\n\r\n",
+        b"3;a=\"x\0\"\r\nabc\r\n0\r\n\r\n",
+    ];
+    for line in lines {
+        let mut buf = BytesMut::from(line);
+        assert_eq!(ChunkedDecoder::new().decode(&mut buf).unwrap_err(), ParseError::InvalidChunk, "{:?}", line);
+    }
+}
@@ -0,0 +1,25 @@
+use super::*;
+
+#[test]
+fn parses_listener_list() {
+    let listeners = parse_listeners("0.0.0.0:443 tls app, 0.0.0.0:80 plain redirect").unwrap();
+    assert_eq!(listeners, vec![
+        ListenerConfig { address: "0.0.0.0:443".to_string(), protocol: Protocol::Tls, service: Service::App },
+        ListenerConfig { address: "0.0.0.0:80".to_string(), protocol: Protocol::Plain, service: Service::Redirect },
+    ]);
+}
+
+#[test]
+fn listener_protocol_and_service_default() {
+    let listeners = parse_listeners("[::]:8443,").unwrap();
+    assert_eq!(listeners, vec![
+        ListenerConfig { address: "[::]:8443".to_string(), protocol: Protocol::Tls, service: Service::App },
+    ]);
+}
+
+#[test]
+fn rejects_unknown_listener_settings() {
+    assert!(parse_listeners("0.0.0.0:80 gopher").is_none());
+    assert!(parse_listeners("0.0.0.0:80 plain proxy").is_none());
+    assert!(parse_listeners("0.0.0.0:80 plain app extra").is_none());
+}
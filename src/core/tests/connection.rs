@@ -1,10 +1,16 @@
 use super::*;
-use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpListener};
+use crate::core::{parser::Method, router::{routes, Router}};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt, DuplexStream}, net::TcpListener, task::JoinHandle};
 use tokio_rustls::{TlsAcceptor, rustls, TlsConnector};
 use rustls::{ServerConfig, RootCertStore, pki_types::{CertificateDer, PrivatePkcs8KeyDer, ServerName}};
 use rcgen::{generate_simple_self_signed};
 use std::sync::Arc;
 
+// A shutdown signal that never fires
+fn no_shutdown() -> watch::Receiver<bool> {
+    watch::channel(false).1
+}
+
 fn generate_tls_config() -> (ServerConfig, rustls::ClientConfig) {
     let cert = generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
 
@@ -38,9 +44,12 @@ async fn handle_client_processes_basic_get_request() {
         let (tcp, _) = listener.accept().await.unwrap();
         let acceptor = TlsAcceptor::from(Arc::new(server_config));
         let tls_stream = acceptor.accept(tcp).await.unwrap();
+        let info = ConnectionInfo::from_tls(&tls_stream);
 
-        let dispatcher = Arc::new(Dispatcher::new());
-        handle_client(tls_stream, dispatcher).await;
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.set_router(routes());
+        let dispatcher = Arc::new(dispatcher);
+        handle_client(tls_stream, info, dispatcher, Arc::new(ConnectionConfig::default()), no_shutdown()).await;
     });
 
     // Simulate a client
@@ -62,3 +71,474 @@ async fn handle_client_processes_basic_get_request() {
     let response = String::from_utf8_lossy(&buf[..n]);
     assert!(response.starts_with("HTTP/1.1 200"));
 }
+
+async fn start_server() -> tokio_rustls::client::TlsStream<tokio::net::TcpStream> {
+    start_server_with(ConnectionConfig::default()).await
+}
+
+async fn start_server_with(config: ConnectionConfig) -> tokio_rustls::client::TlsStream<tokio::net::TcpStream> {
+    start_app(config, routes()).await
+}
+
+async fn start_app(config: ConnectionConfig, router: Router) -> tokio_rustls::client::TlsStream<tokio::net::TcpStream> {
+    start_app_with_handle(config, router).await.0
+}
+
+// Also hands back the server task, for tests that need to see handle_client return
+async fn start_app_with_handle(config: ConnectionConfig, router: Router) -> (tokio_rustls::client::TlsStream<tokio::net::TcpStream>, JoinHandle<()>) {
+    let (server_config, client_config) = generate_tls_config();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+        let tls_stream = acceptor.accept(tcp).await.unwrap();
+        let info = ConnectionInfo::from_tls(&tls_stream);
+
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.set_router(router);
+        let dispatcher = Arc::new(dispatcher);
+        handle_client(tls_stream, info, dispatcher, Arc::new(config), no_shutdown()).await;
+    });
+
+    let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let connector = TlsConnector::from(Arc::new(client_config));
+    let domain = ServerName::try_from("localhost").unwrap();
+    (connector.connect(domain, tcp).await.unwrap(), server)
+}
+
+// Runs handle_client over an in-memory stream, for tests that don't need TLS or a real socket
+fn spawn_duplex(config: ConnectionConfig) -> DuplexStream {
+    spawn_duplex_app(config, routes(), no_shutdown()).0
+}
+
+fn spawn_duplex_app(config: ConnectionConfig, router: Router, shutdown: watch::Receiver<bool>) -> (DuplexStream, JoinHandle<()>) {
+    let (client, server) = tokio::io::duplex(4096);
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.set_router(router);
+    let handle = tokio::spawn(handle_client(server, ConnectionInfo::default(), Arc::new(dispatcher), Arc::new(config), shutdown));
+    (client, handle)
+}
+
+async fn read_to_string(stream: &mut tokio_rustls::client::TlsStream<tokio::net::TcpStream>) -> String {
+    let mut buf = vec![];
+    let _ = stream.read_to_end(&mut buf).await;
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+// Reads exactly one response, using its content-length to find the end.
+async fn read_response(stream: &mut tokio_rustls::client::TlsStream<tokio::net::TcpStream>) -> String {
+    let mut buf = vec![];
+    loop {
+        let text = String::from_utf8_lossy(&buf).into_owned();
+        if let Some(head_end) = text.find("\r\n\r\n") {
+            let length = text[..head_end].lines()
+                .find_map(|l| l.strip_prefix("content-length: "))
+                .map(|l| l.parse::<usize>().unwrap())
+                .unwrap_or(0);
+            if buf.len() >= head_end + 4 + length {
+                return text;
+            }
+        }
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await.unwrap();
+        assert!(n > 0, "connection closed before a full response arrived");
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+#[tokio::test]
+async fn handle_client_decodes_chunked_body_across_reads() {
+    let mut stream = start_server().await;
+
+    stream.write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel").await.unwrap();
+    stream.flush().await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    stream.write_all(b"lo\r\n6;ext=1\r\n world\r\n0\r\n\r\n").await.unwrap();
+    stream.shutdown().await.unwrap();
+
+    let response = read_to_string(&mut stream).await;
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.ends_with("hello world"));
+}
+
+#[tokio::test]
+async fn handle_client_rejects_malformed_chunked_body() {
+    let mut stream = start_server().await;
+
+    stream.write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\nnope\r\n").await.unwrap();
+    stream.shutdown().await.unwrap();
+
+    let response = read_to_string(&mut stream).await;
+    assert!(response.starts_with("HTTP/1.1 400"));
+}
+
+#[tokio::test]
+async fn handle_client_reads_body_larger_than_one_read() {
+    let mut stream = start_server().await;
+
+    let body = vec![b'a'; 20_000];
+    let head = format!("POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n", body.len());
+    stream.write_all(head.as_bytes()).await.unwrap();
+    stream.write_all(&body).await.unwrap();
+    stream.shutdown().await.unwrap();
+
+    let response = read_to_string(&mut stream).await;
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.contains("content-length: 20000\r\n"));
+}
+
+#[tokio::test]
+async fn handle_client_answers_pipelined_requests_in_order() {
+    let mut stream = start_server().await;
+
+    stream.write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nfirstPOST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 6\r\n\r\nsecondGET /page HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    stream.shutdown().await.unwrap();
+
+    let response = read_to_string(&mut stream).await;
+    assert_eq!(response.matches("HTTP/1.1 200").count(), 3);
+    let first = response.find("first").unwrap();
+    let second = response.find("second").unwrap();
+    let third = response.find("<html></html>").unwrap();
+    assert!(first < second && second < third);
+}
+
+#[tokio::test]
+async fn handle_client_keeps_http11_connections_alive() {
+    let mut stream = start_server().await;
+
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let first = read_response(&mut stream).await;
+    assert!(first.starts_with("HTTP/1.1 200"));
+    assert!(first.contains("connection: keep-alive\r\n"));
+    assert!(first.contains("keep-alive: timeout=5, max=99\r\n"));
+
+    stream.write_all(b"GET /page HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let second = read_response(&mut stream).await;
+    assert!(second.ends_with("<html></html>"));
+}
+
+#[tokio::test]
+async fn handle_client_closes_after_connection_close() {
+    let mut stream = start_server().await;
+
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+
+    let response = read_to_string(&mut stream).await;
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.contains("connection: close\r\n"));
+}
+
+#[tokio::test]
+async fn handle_client_closes_http10_without_keep_alive() {
+    let mut stream = start_server().await;
+
+    stream.write_all(b"GET / HTTP/1.0\r\n\r\n").await.unwrap();
+
+    let response = read_to_string(&mut stream).await;
+    assert!(response.contains("connection: close\r\n"));
+}
+
+#[tokio::test]
+async fn handle_client_keeps_http10_alive_on_request() {
+    let mut stream = start_server().await;
+
+    stream.write_all(b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n").await.unwrap();
+    let first = read_response(&mut stream).await;
+    assert!(first.contains("connection: keep-alive\r\n"));
+
+    stream.write_all(b"GET / HTTP/1.0\r\n\r\n").await.unwrap();
+    let second = read_to_string(&mut stream).await;
+    assert!(second.contains("connection: close\r\n"));
+}
+
+#[tokio::test]
+async fn handle_client_closes_after_max_requests() {
+    let config = ConnectionConfig { max_requests: 2, ..ConnectionConfig::default() };
+    let mut stream = start_server_with(config).await;
+
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let first = read_response(&mut stream).await;
+    assert!(first.contains("keep-alive: timeout=5, max=1\r\n"));
+
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let second = read_to_string(&mut stream).await;
+    assert!(second.contains("connection: close\r\n"));
+}
+
+#[tokio::test]
+async fn handle_client_closes_idle_connections() {
+    let config = ConnectionConfig { keep_alive_timeout: std::time::Duration::from_millis(100), ..ConnectionConfig::default() };
+    let mut stream = start_server_with(config).await;
+
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    read_response(&mut stream).await;
+
+    let closed = tokio::time::timeout(std::time::Duration::from_secs(2), read_to_string(&mut stream)).await;
+    assert_eq!(closed.unwrap(), "");
+}
+
+#[tokio::test]
+async fn handle_client_omits_body_for_head() {
+    let mut stream = start_server().await;
+
+    stream.write_all(b"HEAD / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+
+    let response = read_to_string(&mut stream).await;
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.contains("content-length: 5\r\n"));
+    assert!(response.ends_with("\r\n\r\n"));
+}
+
+#[tokio::test]
+async fn handle_client_streams_static_files() {
+    let mut stream = start_server().await;
+
+    stream.write_all(b"GET /static/sample_file.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+
+    let response = read_to_string(&mut stream).await;
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.contains("content-length: 16\r\n"));
+    assert!(response.ends_with("This is a file.\n"));
+}
+
+#[tokio::test]
+async fn handle_client_attaches_connection_info() {
+    let router = Router::new().route(Method::GET, "/info", |req: Request| async move {
+        let info = &req.connection;
+        let tls = info.tls.as_ref().unwrap();
+        let text = format!(
+            "peer={} local={} sni={} version={}",
+            info.peer_addr.unwrap().ip(),
+            info.local_addr.unwrap().ip(),
+            tls.server_name.as_deref().unwrap_or("-"),
+            tls.version.as_deref().unwrap_or("-"),
+        );
+        Response::new().status(StatusCode::Ok).text(&text)
+    });
+    let mut stream = start_app(ConnectionConfig::default(), router).await;
+
+    stream.write_all(b"GET /info HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+    let response = read_to_string(&mut stream).await;
+    assert!(response.ends_with("peer=127.0.0.1 local=127.0.0.1 sni=localhost version=TLSv1_3"), "{}", response);
+}
+
+#[tokio::test]
+async fn handle_client_serves_plain_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let info = ConnectionInfo::from_tcp(&tcp);
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.set_router(Router::new().route(Method::GET, "/", |req: Request| async move {
+            let text = if req.connection.is_tls() { "tls" } else { "plain" };
+            Response::new().status(StatusCode::Ok).text(&text)
+        }));
+        handle_client(tcp, info, Arc::new(dispatcher), Arc::new(ConnectionConfig::default()), no_shutdown()).await;
+    });
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+    let mut buf = vec![];
+    stream.read_to_end(&mut buf).await.unwrap();
+    let response = String::from_utf8_lossy(&buf);
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\nplain"));
+}
+
+#[tokio::test]
+async fn handle_client_works_over_any_stream() {
+    let mut client = spawn_duplex(ConnectionConfig::default());
+
+    client.write_all(b"GET / HTTP/1.0\r\n\r\n").await.unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    let response = String::from_utf8_lossy(&buf);
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("Hello"));
+}
+
+#[tokio::test]
+async fn shutdown_closes_idle_keep_alive_connections() {
+    let (tx, rx) = watch::channel(false);
+    let (mut client, server) = spawn_duplex_app(ConnectionConfig::default(), routes(), rx);
+
+    client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut buf = [0u8; 1024];
+    let n = client.read(&mut buf).await.unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("connection: keep-alive"));
+
+    tx.send(true).unwrap();
+    tokio::time::timeout(std::time::Duration::from_secs(1), server).await.unwrap().unwrap();
+    assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn shutdown_lets_the_current_request_finish() {
+    let (tx, rx) = watch::channel(false);
+    let router = Router::new().route(Method::GET, "/slow", || async {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        Response::new().status(StatusCode::Ok).text(&"done")
+    });
+    let (mut client, _server) = spawn_duplex_app(ConnectionConfig::default(), router, rx);
+
+    client.write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    tx.send(true).unwrap();
+
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    let response = String::from_utf8_lossy(&buf);
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("connection: close\r\n"));
+    assert!(response.ends_with("done"));
+}
+
+fn short_timeouts() -> ConnectionConfig {
+    ConnectionConfig {
+        head_timeout: std::time::Duration::from_millis(100),
+        body_timeout: std::time::Duration::from_millis(100),
+        write_timeout: std::time::Duration::from_millis(100),
+        ..ConnectionConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn slow_head_gets_408() {
+    let mut client = spawn_duplex(short_timeouts());
+
+    // Trickles bytes faster than any per-read timeout would notice, never finishing the head
+    client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+    for _ in 0..5 {
+        if client.write_all(b"X-Slow: a\r\n").await.is_err() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+    }
+
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    let response = String::from_utf8_lossy(&buf);
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"), "{}", response);
+    assert!(response.contains("connection: close\r\n"));
+}
+
+#[tokio::test]
+async fn slow_body_gets_408() {
+    let mut client = spawn_duplex(short_timeouts());
+
+    client.write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nabc").await.unwrap();
+
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert!(String::from_utf8_lossy(&buf).starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+}
+
+#[tokio::test]
+async fn client_not_reading_is_dropped_after_the_write_timeout() {
+    let router = Router::new().route(Method::GET, "/big", || async {
+        Response::new().status(StatusCode::Ok).text(&vec![b'x'; 100_000])
+    });
+    let (mut client, server) = spawn_duplex_app(short_timeouts(), router, no_shutdown());
+
+    client.write_all(b"GET /big HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    tokio::time::timeout(std::time::Duration::from_secs(1), server).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn oversized_body_gets_413_and_closes() {
+    let mut config = ConnectionConfig::default();
+    config.limits.max_body_size = 4;
+    let mut client = spawn_duplex(config);
+
+    client.write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\n").await.unwrap();
+
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    let response = String::from_utf8_lossy(&buf);
+    assert!(response.starts_with("HTTP/1.1 413 Content Too Large\r\n"));
+    assert!(response.contains("connection: close\r\n"));
+}
+
+#[tokio::test]
+async fn too_many_headers_gets_431() {
+    let mut config = ConnectionConfig::default();
+    config.limits.max_headers = 1;
+    let mut client = spawn_duplex(config);
+
+    client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n").await.unwrap();
+
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert!(String::from_utf8_lossy(&buf).starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+}
+
+#[tokio::test]
+async fn smuggled_request_is_rejected_and_not_served() {
+    let mut client = spawn_duplex(ConnectionConfig::default());
+
+    client.write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nGET /secret HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    let response = String::from_utf8_lossy(&buf);
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert_eq!(response.matches("HTTP/1.1 ").count(), 1);
+}
+
+fn streaming_router() -> Router {
+    Router::new().route(Method::GET, "/stream", || async {
+        let (tx, body) = crate::core::body::Body::channel(2);
+        tokio::spawn(async move {
+            let _ = tx.send(Ok(bytes::Bytes::from_static(b"hello "))).await;
+            let _ = tx.send(Ok(bytes::Bytes::from_static(b"world"))).await;
+        });
+        Response::new().status(StatusCode::Ok).body(body)
+    })
+}
+
+#[tokio::test]
+async fn stream_to_http_1_0_is_sent_raw_and_closed() {
+    let (mut client, _server) = spawn_duplex_app(ConnectionConfig::default(), streaming_router(), no_shutdown());
+
+    // Asking for keep-alive doesn't help: closing is the only way to end the body
+    client.write_all(b"GET /stream HTTP/1.0\r\nConnection: keep-alive\r\n\r\n").await.unwrap();
+
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    let response = String::from_utf8_lossy(&buf);
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(!response.contains("transfer-encoding"));
+    assert!(!response.contains("content-length"));
+    assert!(response.contains("connection: close\r\n"));
+    assert!(response.ends_with("\r\n\r\nhello world"));
+}
+
+#[tokio::test]
+async fn stream_to_http_1_1_is_chunked() {
+    let (mut client, _server) = spawn_duplex_app(ConnectionConfig::default(), streaming_router(), no_shutdown());
+
+    client.write_all(b"GET /stream HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    let response = String::from_utf8_lossy(&buf);
+    assert!(response.contains("transfer-encoding: chunked\r\n"));
+    assert!(response.ends_with("6\r\nhello \r\n5\r\nworld\r\n0\r\n\r\n"));
+}
+
+#[tokio::test]
+async fn tls_client_not_reading_is_dropped_after_the_write_timeout() {
+    let router = Router::new().route(Method::GET, "/big", || async {
+        Response::new().status(StatusCode::Ok).text(&vec![b'x'; 50 * 1024 * 1024])
+    });
+    let (mut client, server) = start_app_with_handle(short_timeouts(), router).await;
+
+    client.write_all(b"GET /big HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    // Closing the TLS session has to give up too, not just the write
+    tokio::time::timeout(std::time::Duration::from_secs(3), server).await.unwrap().unwrap();
+}
@@ -0,0 +1,50 @@
+use super::*;
+
+#[derive(Debug, PartialEq)]
+struct UserId(u64);
+
+#[test]
+fn values_are_keyed_by_type() {
+    let mut ext = Extensions::new();
+    ext.insert(UserId(7));
+    ext.insert("request-1".to_string());
+
+    assert_eq!(ext.get::<UserId>(), Some(&UserId(7)));
+    assert_eq!(ext.get::<String>().unwrap(), "request-1");
+    assert!(ext.get::<u32>().is_none());
+    assert_eq!(ext.len(), 2);
+}
+
+#[test]
+fn insert_replaces_same_type() {
+    let mut ext = Extensions::new();
+    ext.insert(UserId(1));
+    ext.insert(UserId(2));
+
+    assert_eq!(ext.len(), 1);
+    assert_eq!(*ext.remove::<UserId>().unwrap(), UserId(2));
+    assert!(!ext.contains::<UserId>());
+}
+
+#[test]
+fn clones_share_values() {
+    let mut ext = Extensions::new();
+    ext.insert_arc(Arc::new(UserId(3)));
+    let cloned = ext.clone();
+
+    assert!(Arc::ptr_eq(&ext.get_arc::<UserId>().unwrap(), &cloned.get_arc::<UserId>().unwrap()));
+}
+
+#[test]
+fn extend_overrides_existing_values() {
+    let mut ext = Extensions::new();
+    ext.insert(UserId(1));
+    ext.insert(5u32);
+
+    let mut other = Extensions::new();
+    other.insert(UserId(2));
+    ext.extend(&other);
+
+    assert_eq!(ext.get::<UserId>(), Some(&UserId(2)));
+    assert_eq!(ext.get::<u32>(), Some(&5));
+}
@@ -0,0 +1,110 @@
+use super::*;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Search {
+    q: String,
+    page: u32,
+}
+
+fn request_with_body(content_type: &str, body: &str) -> Request {
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", content_type);
+    Request {
+        headers,
+        body: body.as_bytes().to_vec(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn query_deserializes_typed_fields() {
+    let mut req = Request::default();
+    req.query.insert("q".to_string(), "rust lang".to_string());
+    req.query.insert("page".to_string(), "2".to_string());
+
+    let Query(search) = Query::<Search>::from_request(&req).unwrap();
+    assert_eq!(search, Search { q: "rust lang".to_string(), page: 2 });
+}
+
+#[test]
+fn query_from_a_parsed_request_keeps_encoded_characters() {
+    let buf = bytes::BytesMut::from(&b"GET /search?q=caf%C3%A9+%26+cr%C3%AApes%3D&page=2 HTTP/1.1\r\n\r\n"[..]);
+    let (_, req) = crate::core::parser::parse_request(&buf).unwrap();
+
+    let Query(search) = Query::<Search>::from_request(&req).unwrap();
+    assert_eq!(search, Search { q: "café & crêpes=".to_string(), page: 2 });
+}
+
+#[test]
+fn bad_query_is_400() {
+    let mut req = Request::default();
+    req.query.insert("q".to_string(), "rust".to_string());
+    req.query.insert("page".to_string(), "two".to_string());
+
+    let rejection = Query::<Search>::from_request(&req).unwrap_err();
+    assert_eq!(rejection.status, StatusCode::BadRequest);
+}
+
+#[test]
+fn path_deserializes_route_params() {
+    #[derive(Deserialize)]
+    struct UserPath {
+        id: u64,
+    }
+
+    let mut req = Request::default();
+    req.params.insert("id".to_string(), "42".to_string());
+    let Path(path) = Path::<UserPath>::from_request(&req).unwrap();
+    assert_eq!(path.id, 42);
+}
+
+#[test]
+fn json_body_rejections() {
+    let req = request_with_body("application/json; charset=utf-8", r#"{"q": "rust", "page": 1}"#);
+    let Json(search) = Json::<Search>::from_request(&req).unwrap();
+    assert_eq!(search.page, 1);
+
+    let req = request_with_body("text/plain", r#"{"q": "rust", "page": 1}"#);
+    assert_eq!(Json::<Search>::from_request(&req).unwrap_err().status, StatusCode::UnsupportedMediaType);
+
+    let req = request_with_body("application/json", r#"{"q": "rust", "#);
+    assert_eq!(Json::<Search>::from_request(&req).unwrap_err().status, StatusCode::BadRequest);
+
+    let req = request_with_body("application/json", r#"{"q": "rust", "page": "one"}"#);
+    assert_eq!(Json::<Search>::from_request(&req).unwrap_err().status, StatusCode::UnprocessableContent);
+}
+
+#[test]
+fn form_body_rejections() {
+    let req = request_with_body("application/x-www-form-urlencoded", "q=rust+lang&page=3");
+    let Form(search) = Form::<Search>::from_request(&req).unwrap();
+    assert_eq!(search, Search { q: "rust lang".to_string(), page: 3 });
+
+    let req = request_with_body("application/json", "q=rust&page=3");
+    assert_eq!(Form::<Search>::from_request(&req).unwrap_err().status, StatusCode::UnsupportedMediaType);
+
+    let req = request_with_body("application/x-www-form-urlencoded", "q=rust");
+    assert_eq!(Form::<Search>::from_request(&req).unwrap_err().status, StatusCode::UnprocessableContent);
+}
+
+#[test]
+fn state_is_downcast_to_its_type() {
+    let mut req = Request::default();
+    req.extensions.insert(7u32);
+    let State(n) = State::<u32>::from_request(&req).unwrap();
+    assert_eq!(*n, 7);
+
+    let rejection = State::<String>::from_request(&req).unwrap_err();
+    assert_eq!(rejection.status, StatusCode::InternalError);
+}
+
+#[test]
+fn optional_extension_is_none_when_missing() {
+    let mut req = Request::default();
+    assert!(Option::<Extension<String>>::from_request(&req).unwrap().is_none());
+
+    req.extensions.insert("user-1".to_string());
+    let Extension(user) = Option::<Extension<String>>::from_request(&req).unwrap().unwrap();
+    assert_eq!(*user, "user-1");
+}
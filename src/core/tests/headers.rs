@@ -0,0 +1,58 @@
+use super::*;
+
+#[test]
+fn lookup_is_case_insensitive() {
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Length", "10");
+
+    assert_eq!(headers.get("content-length"), Some("10"));
+    assert_eq!(headers.get("CONTENT-LENGTH"), Some("10"));
+    assert!(headers.contains_key("Content-length"));
+}
+
+#[test]
+fn append_keeps_repeated_values() {
+    let mut headers = HeaderMap::new();
+    headers.append("Set-Cookie", "a=1");
+    headers.append("set-cookie", "b=2");
+
+    assert_eq!(headers.get_all("Set-Cookie").collect::<Vec<_>>(), ["a=1", "b=2"]);
+    assert_eq!(headers.get("Set-Cookie"), Some("a=1"));
+    assert_eq!(headers.len(), 2);
+}
+
+#[test]
+fn insert_replaces_all_values_in_place() {
+    let mut headers = HeaderMap::new();
+    headers.append("X-First", "1");
+    headers.append("Vary", "Origin");
+    headers.append("X-Last", "2");
+    headers.append("vary", "Accept");
+
+    headers.insert("VARY", "Cookie");
+
+    let entries: Vec<_> = headers.iter().collect();
+    assert_eq!(entries, [("X-First", "1"), ("Vary", "Cookie"), ("X-Last", "2")]);
+}
+
+#[test]
+fn iterates_in_insertion_order() {
+    let mut headers = HeaderMap::new();
+    headers.insert("b", "2");
+    headers.insert("a", "1");
+    headers.append("c", "3");
+
+    let names: Vec<_> = headers.iter().map(|(k, _)| k).collect();
+    assert_eq!(names, ["b", "a", "c"]);
+}
+
+#[test]
+fn remove_drops_every_value() {
+    let mut headers = HeaderMap::new();
+    headers.append("Set-Cookie", "a=1");
+    headers.append("Set-Cookie", "b=2");
+
+    assert_eq!(headers.remove("set-cookie"), Some("a=1".to_string()));
+    assert!(headers.is_empty());
+    assert_eq!(headers.remove("set-cookie"), None);
+}
@@ -21,6 +21,34 @@ fn parses_basic_post_request() {
     assert_eq!(req.1.version, "HTTP/1.0");
 }
 
+#[test]
+fn keeps_the_raw_target() {
+    let buf = BytesMut::from(&b"GET /a%20b?x=1&x=2&flag HTTP/1.1\r\n\r\n"[..]);
+    let req = parse_request(&buf).unwrap().1;
+    assert_eq!(req.target, "/a%20b?x=1&x=2&flag");
+    assert_eq!(req.path, "/a%20b");
+}
+
+#[test]
+fn query_is_split_before_it_is_decoded() {
+    let buf = BytesMut::from(&b"GET /search?q=a%26b&r=x%3Dy&s=caf%C3%A9&t=a+b%2Bc&flag HTTP/1.1\r\n\r\n"[..]);
+    let req = parse_request(&buf).unwrap().1;
+    assert_eq!(req.query["q"], "a&b");
+    assert_eq!(req.query["r"], "x=y");
+    assert_eq!(req.query["s"], "café");
+    assert_eq!(req.query["t"], "a b+c");
+    assert_eq!(req.query["flag"], "");
+    assert_eq!(req.query.len(), 5);
+}
+
+#[test]
+fn rejects_badly_encoded_targets() {
+    for target in ["/a%2G", "/a?q=%", "/a?q=%FF"] {
+        let buf = BytesMut::from(format!("GET {} HTTP/1.1\r\n\r\n", target).as_bytes());
+        assert_eq!(parse_request(&buf).unwrap_err(), ParseError::InvalidTarget, "{}", target);
+    }
+}
+
 #[test]
 fn errors_on_missing_parts() {
     let mut buf = BytesMut::new();
@@ -43,7 +71,7 @@ fn parses_single_header() {
     let mut buf = BytesMut::new();
     buf.extend_from_slice(b"Host: localhost\r\n\r\n");
     let mut idx = 0;
-    let headers = generate_headers(&mut buf, &mut idx);
+    let headers = generate_headers(&mut buf, &mut idx).unwrap();
 
     assert_eq!(headers.get("Host").unwrap(), "localhost");
     assert_eq!(idx, 19);
@@ -54,7 +82,7 @@ fn handles_multiple_headers() {
     let mut buf = BytesMut::new();
     buf.extend_from_slice(b"Content-Type: text/plain\r\nUser-Agent: Test\r\n\r\n");
     let mut idx = 0;
-    let headers = generate_headers(&mut buf, &mut idx);
+    let headers = generate_headers(&mut buf, &mut idx).unwrap();
 
     assert_eq!(headers.get("Content-Type").unwrap(), "text/plain");
     assert_eq!(headers.get("User-Agent").unwrap(), "Test");
@@ -62,70 +90,281 @@ fn handles_multiple_headers() {
 }
 
 #[test]
-fn skips_malformed_lines_and_parses_valid_ones() {
+fn errors_on_line_without_colon() {
     let mut buf = BytesMut::new();
     buf.extend_from_slice(b"Good-Header: value\r\nBadHeaderLine\r\nAnother: ok\r\n\r\n");
     let mut idx = 0;
-    let headers = generate_headers(&mut buf, &mut idx);
+    assert_eq!(generate_headers(&mut buf, &mut idx).unwrap_err(), ParseError::InvalidHeader);
+}
 
-    assert_eq!(headers.get("Good-Header").unwrap(), "value");
-    assert_eq!(headers.get("Another").unwrap(), "ok");
-    assert!(!headers.contains_key("nBadHeaderLine"));
-    assert_eq!(idx, 50);
+#[test]
+fn trims_optional_whitespace_around_values() {
+    let mut buf = BytesMut::from(&b"Host:localhost\r\nAccept: \t text/html \t\r\n\r\n"[..]);
+    let headers = generate_headers(&mut buf, &mut 0).unwrap();
+    assert_eq!(headers.get("Host").unwrap(), "localhost");
+    assert_eq!(headers.get("Accept").unwrap(), "text/html");
 }
 
-// generate_body tests
 #[test]
-fn extracts_body_correctly() {
+fn errors_on_malformed_header_lines() {
+    let lines: [&[u8]; 5] = [
+        b"Host : localhost\r\n\r\n",
+        b"Bad Name: value\r\n\r\n",
+        b"Host: localhost\r\n folded\r\n\r\n",
+        b"X-Nul: a\0b\r\n\r\n",
+        b"X-Bare: a\nInjected: b\r\n\r\n",
+    ];
+    for line in lines {
+        let mut buf = BytesMut::from(line);
+        assert_eq!(generate_headers(&mut buf, &mut 0).unwrap_err(), ParseError::InvalidHeader, "{:?}", line);
+    }
+}
+
+#[test]
+fn keeps_repeated_headers_and_ignores_name_case() {
     let mut buf = BytesMut::new();
-    buf.extend_from_slice(b"Header: test\r\n\r\nbody data");
-    let idx = 16;
-    let length = "9".to_string();
+    buf.extend_from_slice(b"Accept: text/html\r\ncontent-length: 4\r\naccept: application/json\r\n\r\n");
+    let mut idx = 0;
+    let headers = generate_headers(&mut buf, &mut idx).unwrap();
+
+    assert_eq!(headers.get("Content-Length").unwrap(), "4");
+    assert_eq!(headers.get_all("ACCEPT").collect::<Vec<_>>(), ["text/html", "application/json"]);
+}
 
-    let body = generate_body(Some(&length), &mut buf, idx);
-    assert_eq!(body, b"body data")
+// RequestParser tests
+fn expect_complete(status: ParseStatus) -> Request {
+    match status {
+        ParseStatus::Complete(req) => req,
+        other => panic!("expected a complete request, got {:?}", other),
+    }
+}
+
+#[test]
+fn extracts_body_correctly() {
+    let mut buf = BytesMut::from(&b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 9\r\n\r\nbody data"[..]);
+    let req = expect_complete(RequestParser::new().parse(&mut buf));
+
+    assert_eq!(req.body, b"body data");
+    assert!(buf.is_empty());
 }
 
 #[test]
 fn returns_empty_vec_when_length_is_zero() {
-    let mut buf = BytesMut::new();
-    buf.extend_from_slice(b"Header: test\r\n\r\n");
+    let mut buf = BytesMut::from(&b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n"[..]);
+    let req = expect_complete(RequestParser::new().parse(&mut buf));
+    assert!(req.body.is_empty());
+}
 
-    let idx = 16;
-    let length = "0".to_string();
+#[test]
+fn leaves_bytes_unconsumed_when_length_is_none() {
+    let mut buf = BytesMut::from(&b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\nsome data here"[..]);
+    let req = expect_complete(RequestParser::new().parse(&mut buf));
 
-    let body = generate_body(Some(&length), &mut buf, idx);
-    assert!(body.is_empty());
+    assert!(req.body.is_empty());
+    assert_eq!(&buf[..], b"some data here");
 }
 
 #[test]
-fn extracts_remaining_bytes_when_length_is_none() {
-    let mut buf = BytesMut::new();
-    buf.extend_from_slice(b"Header: test\r\n\r\nsome data here");
+fn waits_for_rest_of_body_when_length_is_larger_than_buffer() {
+    let mut parser = RequestParser::new();
+    let mut buf = BytesMut::from(&b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nshort"[..]);
 
-    let idx = 16;
+    assert!(matches!(parser.parse(&mut buf), ParseStatus::Partial));
+    buf.extend_from_slice(b" body");
+    let req = expect_complete(parser.parse(&mut buf));
+    assert_eq!(req.body, b"short body");
+}
+
+#[test]
+fn waits_for_rest_of_head() {
+    let mut parser = RequestParser::new();
+    let mut buf = BytesMut::from(&b"GET /index.html HTTP/1.1\r\nHo"[..]);
+
+    assert!(matches!(parser.parse(&mut buf), ParseStatus::Partial));
+    buf.extend_from_slice(b"st: localhost\r\n\r\n");
+    let req = expect_complete(parser.parse(&mut buf));
+    assert_eq!(req.path, "/index.html");
+    assert_eq!(req.headers.get("Host").unwrap(), "localhost");
+}
 
-    let body = generate_body(None, &mut buf, idx);
-    assert!(body.is_empty());
+#[test]
+fn parses_request_without_headers() {
+    let mut buf = BytesMut::from(&b"GET / HTTP/1.0\r\n\r\n"[..]);
+    let req = expect_complete(RequestParser::new().parse(&mut buf));
+    assert!(req.headers.is_empty());
 }
+
 #[test]
-fn handles_length_larger_than_remaining_data_gracefully() {
-    let mut buf = BytesMut::new();
-    buf.extend_from_slice(b"Header: test\r\n\r\nshort");
+fn parses_chunked_body_and_trailers() {
+    let mut parser = RequestParser::new();
+    let mut buf = BytesMut::from(&b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n"[..]);
+
+    assert!(matches!(parser.parse(&mut buf), ParseStatus::Partial));
+    buf.extend_from_slice(b"0\r\nX-Trailer: yes\r\nAuthorization: Bearer x\r\n\r\n");
+    let req = expect_complete(parser.parse(&mut buf));
+    assert_eq!(req.body, b"abc");
+    assert_eq!(req.trailers.get("X-Trailer").unwrap(), "yes");
+    // Trailers can't add to what the header section said
+    assert!(!req.headers.contains_key("X-Trailer"));
+    assert!(!req.headers.contains_key("Authorization"));
+}
+
+#[test]
+fn errors_on_invalid_content_length() {
+    let mut buf = BytesMut::from(&b"POST / HTTP/1.1\r\nContent-Length: abc\r\n\r\n"[..]);
+    assert!(matches!(RequestParser::new().parse(&mut buf), ParseStatus::Error(_)));
+}
+
+#[test]
+fn errors_on_malformed_request_line() {
+    let mut buf = BytesMut::from(&b"GET /missingversion\r\n\r\n"[..]);
+    assert!(matches!(RequestParser::new().parse(&mut buf), ParseStatus::Error(_)));
+}
+
+fn expect_error(parser: &mut RequestParser, bytes: &[u8]) -> ParseError {
+    let mut buf = BytesMut::from(bytes);
+    match parser.parse(&mut buf) {
+        ParseStatus::Error(e) => e,
+        other => panic!("expected an error, got {:?}", other),
+    }
+}
+
+fn small_limits() -> Limits {
+    Limits { max_uri_length: 32, max_header_bytes: 64, max_headers: 2, max_body_size: 8 }
+}
+
+#[test]
+fn rejects_long_target_with_414() {
+    let mut parser = RequestParser::with_limits(small_limits());
+    let request = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(40));
+    let err = expect_error(&mut parser, request.as_bytes());
+    assert_eq!(err, ParseError::UriTooLong);
+    assert_eq!(err.status(), StatusCode::UriTooLong);
+}
+
+#[test]
+fn rejects_long_request_line_before_it_ends() {
+    let mut parser = RequestParser::with_limits(small_limits());
+    let request = format!("GET /{}", "a".repeat(100));
+    assert_eq!(expect_error(&mut parser, request.as_bytes()), ParseError::UriTooLong);
+}
+
+#[test]
+fn rejects_oversized_header_section_with_431() {
+    let mut parser = RequestParser::with_limits(small_limits());
+    // Never finished, so only the running size check can catch it
+    let request = format!("GET / HTTP/1.1\r\nX-Big: {}", "a".repeat(100));
+    let err = expect_error(&mut parser, request.as_bytes());
+    assert_eq!(err, ParseError::HeadersTooLarge);
+    assert_eq!(err.status(), StatusCode::RequestHeaderFieldsTooLarge);
+}
+
+#[test]
+fn rejects_too_many_headers_with_431() {
+    let mut parser = RequestParser::with_limits(small_limits());
+    let err = expect_error(&mut parser, b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n");
+    assert_eq!(err, ParseError::TooManyHeaders);
+    assert_eq!(err.status(), StatusCode::RequestHeaderFieldsTooLarge);
+}
+
+#[test]
+fn rejects_large_content_length_with_413() {
+    let mut parser = RequestParser::with_limits(small_limits());
+    let err = expect_error(&mut parser, b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 9\r\n\r\n");
+    assert_eq!(err, ParseError::BodyTooLarge);
+    assert_eq!(err.status(), StatusCode::ContentTooLarge);
+}
+
+#[test]
+fn rejects_large_chunked_body_with_413() {
+    let mut parser = RequestParser::with_limits(small_limits());
+    let err = expect_error(&mut parser, b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nabcde\r\n5\r\nfghij\r\n0\r\n\r\n");
+    assert_eq!(err, ParseError::BodyTooLarge);
+}
+
+#[test]
+fn accepts_request_at_the_limits() {
+    let mut parser = RequestParser::with_limits(small_limits());
+    let mut buf = BytesMut::from(&b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 8\r\n\r\n12345678"[..]);
+    assert_eq!(expect_complete(parser.parse(&mut buf)).body, b"12345678");
+}
+
+fn expect_rejected(bytes: &[u8], expected: ParseError) {
+    let err = expect_error(&mut RequestParser::new(), bytes);
+    assert_eq!(err, expected, "{}", String::from_utf8_lossy(bytes));
+    assert_eq!(err.status(), StatusCode::BadRequest);
+}
+
+#[test]
+fn rejects_content_length_with_transfer_encoding() {
+    expect_rejected(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n", ParseError::AmbiguousLength);
+}
+
+#[test]
+fn rejects_transfer_encoding_not_ending_in_chunked() {
+    expect_rejected(b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked, gzip\r\n\r\n", ParseError::AmbiguousLength);
+    expect_rejected(b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n\r\n", ParseError::AmbiguousLength);
+    expect_rejected(b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked, ,\r\n\r\n", ParseError::AmbiguousLength);
+    expect_rejected(b"POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n", ParseError::AmbiguousLength);
+}
+
+#[test]
+fn rejects_other_transfer_codings_with_501() {
+    let err = expect_error(&mut RequestParser::new(), b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip, chunked\r\n\r\n");
+    assert_eq!(err, ParseError::UnsupportedTransferCoding);
+    assert_eq!(err.status(), StatusCode::NotImplemented);
+}
+
+#[test]
+fn rejects_conflicting_content_lengths() {
+    expect_rejected(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\nContent-Length: 4\r\n\r\nabcd", ParseError::AmbiguousLength);
+    expect_rejected(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 3, 4\r\n\r\nabcd", ParseError::AmbiguousLength);
+    expect_rejected(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: +3\r\n\r\nabc", ParseError::InvalidContentLength);
+}
+
+#[test]
+fn accepts_repeated_identical_content_lengths() {
+    let mut buf = BytesMut::from(&b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\nContent-Length: 3\r\n\r\nabc"[..]);
+    assert_eq!(expect_complete(RequestParser::new().parse(&mut buf)).body, b"abc");
+}
+
+#[test]
+fn requires_exactly_one_host_on_http_1_1() {
+    expect_rejected(b"GET / HTTP/1.1\r\n\r\n", ParseError::InvalidHost);
+    expect_rejected(b"GET / HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n", ParseError::InvalidHost);
+}
 
-    let idx = 16;
-    let length = "999".to_string();
+#[test]
+fn rejects_bare_lf_line_endings() {
+    expect_rejected(b"GET / HTTP/1.1\nHost: a\n\n", ParseError::InvalidHeader);
+}
+
+#[test]
+fn rejects_extra_spaces_in_request_line() {
+    expect_rejected(b"GET  / HTTP/1.1\r\nHost: a\r\n\r\n", ParseError::InvalidRequestLine);
+    expect_rejected(b"GET / HTTP/1.1 \r\nHost: a\r\n\r\n", ParseError::InvalidRequestLine);
+}
+
+#[test]
+fn rejects_other_major_versions_with_505() {
+    let err = expect_error(&mut RequestParser::new(), b"GET / HTTP/2.0\r\n\r\n");
+    assert_eq!(err, ParseError::UnsupportedVersion);
+    assert_eq!(err.status(), StatusCode::HttpVersionNotSupported);
+}
 
-    let body = generate_body(Some(&length), &mut buf, idx);
-    assert_eq!(body, b"short");
+#[test]
+fn maps_malformed_requests_to_400() {
+    let err = expect_error(&mut RequestParser::new(), b"GET / HTTX/1.1\r\n\r\n");
+    assert_eq!(err, ParseError::InvalidRequestLine);
+    assert_eq!(err.status(), StatusCode::BadRequest);
 }
 
 // generate_cookies tests
 use std::collections::HashMap;
 
 fn build_request_with_cookie_header(cookie_header: &str) -> Request {
-    let mut headers = HashMap::new();
-    headers.insert("Cookie".to_string(), cookie_header.to_string());
+    let mut headers = HeaderMap::new();
+    headers.insert("Cookie", cookie_header);
 
     Request {
         method: Method::GET,
@@ -135,6 +374,7 @@ fn build_request_with_cookie_header(cookie_header: &str) -> Request {
         headers,
         body: Vec::new(),
         cookies: None,
+        ..Default::default()
     }
 }
 
@@ -154,6 +394,15 @@ fn parses_multiple_cookies() {
     assert_eq!(cookies.get("c").unwrap(), "3");
 }
 
+#[test]
+fn parses_cookies_from_repeated_cookie_headers() {
+    let mut req = build_request_with_cookie_header("a=1");
+    req.headers.append("cookie", "b=2");
+    let cookies = generate_cookies(&req);
+    assert_eq!(cookies.get("a").unwrap(), "1");
+    assert_eq!(cookies.get("b").unwrap(), "2");
+}
+
 #[test]
 fn returns_empty_map_when_no_cookie_header_present() {
     let req = Request {
@@ -161,9 +410,10 @@ fn returns_empty_map_when_no_cookie_header_present() {
         path: "/".to_string(),
         version: "HTTP/1.1".to_string(),
         query: HashMap::new(),
-        headers: HashMap::new(),
+        headers: HeaderMap::new(),
         body: Vec::new(),
         cookies: None,
+        ..Default::default()
     };
 
     let cookies = generate_cookies(&req);
@@ -192,9 +442,49 @@ fn ignores_invalid_percent_sequence() {
     assert!(result.is_err());
 }
 
+#[test]
+fn decodes_utf8_and_rejects_invalid_utf8() {
+    assert_eq!(percent_decoder("Jos%C3%A9").unwrap(), "José");
+    assert_eq!(percent_decoder("%E2%9C%93").unwrap(), "✓");
+    assert_eq!(percent_decoder("%C3").unwrap_err(), ParseError::InvalidTarget);
+    assert_eq!(percent_decoder("%FF").unwrap_err(), ParseError::InvalidTarget);
+}
+
 #[test]
 fn handles_no_encodings_gracefully() {
     let input = "cleanpath".to_string();
     let decoded = percent_decoder(&input);
     assert_eq!(decoded.unwrap(), "cleanpath");
 }
+
+// Method tests
+#[test]
+fn parses_all_standard_methods() {
+    for (name, method) in [
+        ("HEAD", Method::HEAD),
+        ("OPTIONS", Method::OPTIONS),
+        ("PATCH", Method::PATCH),
+        ("TRACE", Method::TRACE),
+        ("CONNECT", Method::CONNECT),
+    ] {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(format!("{} / HTTP/1.1\r\n\r\n", name).as_bytes());
+        assert_eq!(parse_request(&buf).unwrap().1.method, method);
+    }
+}
+
+#[test]
+fn parses_extension_method() {
+    let mut buf = BytesMut::new();
+    buf.extend_from_slice(b"PROPFIND /files HTTP/1.1\r\n\r\n");
+    let req = parse_request(&buf).unwrap();
+    assert_eq!(req.1.method, Method::Other("PROPFIND".to_string()));
+    assert_eq!(req.1.method.to_string(), "PROPFIND");
+}
+
+#[test]
+fn errors_on_invalid_method_token() {
+    let mut buf = BytesMut::new();
+    buf.extend_from_slice(b"GE(T / HTTP/1.1\r\n\r\n");
+    assert!(parse_request(&buf).is_err());
+}
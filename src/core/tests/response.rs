@@ -47,11 +47,11 @@ fn json_serialization_failure_fallback() {
         }
     }
 
-    let data = BadJson { bad: std::f64::NAN };
+    let data = BadJson { bad: f64::NAN };
     let mut res = Response::new().status(StatusCode::Ok).json(&data);
     let body = String::from_utf8_lossy(&res.finalize()).into_owned();
 
-    assert!(body.contains("HTTP/1.1 500 Internal Error"));
+    assert!(body.contains("HTTP/1.1 500 Internal Server Error"));
     assert!(body.contains("x-serialize-error: true"));
     assert!(body.contains("{\"Error\": \"Could not serialize JSON\"}"));
 }
@@ -65,6 +65,56 @@ fn valid_and_invalikd_custom_headers() {
     assert!(!bad.headers.contains_key("bad:header"));
 }
 
+#[test]
+fn header_values_cannot_split_the_response() {
+    let mut res = Response::new()
+        .status(StatusCode::Found)
+        .header("Location", "/ok\r\nSet-Cookie: evil=1")
+        .append_header("X-Nul", "a\0b");
+    assert!(!res.headers.contains_key("location"));
+    assert!(!res.headers.contains_key("x-nul"));
+    assert_eq!(res.header_errors.len(), 2);
+
+    let out = String::from_utf8_lossy(&res.finalize()).into_owned();
+    assert!(!out.contains("evil"));
+}
+
+#[test]
+fn headers_set_directly_are_checked_when_serialised() {
+    let mut res = Response::new().status(StatusCode::Ok).text(&"ok");
+    res.headers.insert("Location", "/ok\r\nSet-Cookie: evil=1");
+    res.headers.append("Bad Header", "x");
+    res.headers.append("X-Fine", "yes");
+
+    let out = String::from_utf8_lossy(&res.finalize()).into_owned();
+    assert!(!out.contains("evil"));
+    assert!(!out.contains("Bad Header"));
+    assert!(out.contains("X-Fine: yes\r\n"));
+    assert_eq!(res.header_errors, vec![
+        HeaderError::InvalidValue { name: "Location".to_string(), value: "/ok\r\nSet-Cookie: evil=1".to_string() },
+        HeaderError::InvalidName("Bad Header".to_string()),
+    ]);
+}
+
+#[test]
+fn invalid_headers_are_recorded() {
+    let res = Response::new().header("Bad Header", "x").header("X-Tab", "a\tb");
+    assert_eq!(res.header_errors, vec![HeaderError::InvalidName("Bad Header".to_string())]);
+    assert_eq!(res.headers.get("x-tab").unwrap(), "a\tb");
+}
+
+#[test]
+fn try_header_returns_the_error() {
+    let mut res = Response::new();
+    assert_eq!(
+        res.try_header("Location", "/a\nb"),
+        Err(HeaderError::InvalidValue { name: "Location".to_string(), value: "/a\nb".to_string() })
+    );
+    assert!(res.try_append_header("Set-Cookie", "a=1").is_ok());
+    assert!(res.header_errors.is_empty());
+    assert_eq!(res.headers.get("set-cookie").unwrap(), "a=1");
+}
+
 #[test]
 fn not_found_response() {
     let mut res = Response::not_found();
@@ -94,3 +144,98 @@ fn overriding_headers_works() {
     assert!(out.contains("content-type: override/type"));
     assert!(!out.contains("text/plain; charset=utf-8"));
 }
+
+#[test]
+fn appended_headers_are_all_written_in_order() {
+    let mut res = Response::new()
+        .header("X-First", "1")
+        .append_header("Set-Cookie", "a=1")
+        .append_header("Set-Cookie", "b=2");
+    let out = String::from_utf8_lossy(&res.finalize()).into_owned();
+
+    let first = out.find("x-first: 1\r\n").unwrap();
+    let cookie_a = out.find("set-cookie: a=1\r\n").unwrap();
+    let cookie_b = out.find("set-cookie: b=2\r\n").unwrap();
+    assert!(first < cookie_a && cookie_a < cookie_b);
+}
+
+#[test]
+fn stream_body_is_sent_chunked() {
+    let (_tx, body) = Body::channel(1);
+    let mut res = Response::new().status(StatusCode::Ok).header("Content-Length", "99").body(body);
+    let out = String::from_utf8_lossy(&res.finalize_head()).into_owned();
+
+    assert!(out.contains("transfer-encoding: chunked\r\n"));
+    assert!(!out.contains("content-length"));
+}
+
+#[test]
+fn status_codes_convert_both_ways() {
+    assert_eq!(StatusCode::from_u16(201), Some(StatusCode::Created));
+    assert_eq!(StatusCode::from_u16(429), Some(StatusCode::TooManyRequests));
+    assert_eq!(StatusCode::from_u16(299), None);
+    assert_eq!(StatusCode::PermanentRedirect.as_u16(), 308);
+    assert_eq!(u16::from(&StatusCode::ServiceUnavailable), 503);
+    assert_eq!(StatusCode::UnprocessableContent.reason(), "Unprocessable Content");
+}
+
+#[test]
+fn custom_status_uses_its_own_reason() {
+    let mut res = Response::new().status(StatusCode::Custom(599, "Network Timeout".to_string()));
+    let out = String::from_utf8_lossy(&res.finalize()).into_owned();
+
+    assert!(out.starts_with("HTTP/1.1 599 Network Timeout\r\n"));
+}
+
+#[test]
+fn invalid_custom_status_becomes_500() {
+    for status in [
+        StatusCode::Custom(299, "OK\r\nX-Injected: 1".to_string()),
+        StatusCode::Custom(42, "Too Short".to_string()),
+        StatusCode::Custom(1000, "Too Long".to_string()),
+    ] {
+        assert!(!status.is_valid());
+        let mut res = Response::new().status(status);
+        let out = String::from_utf8_lossy(&res.finalize()).into_owned();
+        assert!(out.starts_with("HTTP/1.1 500 Internal Server Error\r\n"), "{}", out);
+        assert!(!out.contains("Injected"));
+    }
+}
+
+#[test]
+fn no_content_response_has_no_body_or_length() {
+    let mut res = Response::new().status(StatusCode::NoContent).text(&"ignored");
+    let out = String::from_utf8_lossy(&res.finalize()).into_owned();
+
+    assert!(out.starts_with("HTTP/1.1 204 No Content\r\n"));
+    assert!(!out.contains("content-length"));
+    assert!(out.ends_with("\r\n\r\n"));
+}
+
+#[test]
+fn not_modified_keeps_explicit_length_but_drops_body() {
+    let mut res = Response::new()
+        .status(StatusCode::NotModified)
+        .header("Content-Length", "42");
+    let out = String::from_utf8_lossy(&res.finalize()).into_owned();
+
+    assert!(out.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+    assert!(out.contains("content-length: 42\r\n"));
+    assert!(out.ends_with("\r\n\r\n"));
+}
+
+#[test]
+fn informational_responses_are_bodiless() {
+    assert!(StatusCode::Continue.is_bodiless());
+    assert!(StatusCode::Custom(199, "Whatever".to_string()).is_bodiless());
+    assert!(!StatusCode::Ok.is_bodiless());
+}
+
+#[test]
+fn unknown_length_is_not_chunked_for_http_1_0() {
+    let (_tx, body) = Body::channel(1);
+    let mut res = Response::new().status(StatusCode::Ok).header("Transfer-Encoding", "chunked").body(body);
+    let head = String::from_utf8_lossy(&res.finalize_head_for("HTTP/1.0")).into_owned();
+    assert!(!head.contains("transfer-encoding"));
+    assert!(!head.contains("content-length"));
+}
@@ -0,0 +1,285 @@
+use super::*;
+use crate::core::headers::HeaderMap;
+use std::collections::HashMap;
+
+fn make_request(method: Method, path: &str) -> Request {
+    Request {
+        method,
+        path: path.to_string(),
+        version: "HTTP/1.1".to_string(),
+        query: HashMap::new(),
+        headers: HeaderMap::new(),
+        body: vec![],
+        cookies: None,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn head_is_routed_to_get_handler() {
+    let mut res = routes().handle(make_request(Method::HEAD, "/")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+
+    let head = String::from_utf8_lossy(&res.finalize_head()).into_owned();
+    assert!(head.contains("content-length: 5\r\n"));
+    assert!(head.ends_with("\r\n\r\n"));
+}
+
+#[tokio::test]
+async fn options_lists_allowed_methods() {
+    let res = routes().handle(make_request(Method::OPTIONS, "/")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+    assert_eq!(res.headers.get("allow").unwrap(), "GET, HEAD, POST, OPTIONS");
+}
+
+#[tokio::test]
+async fn options_on_unknown_path_is_not_found() {
+    let res = routes().handle(make_request(Method::OPTIONS, "/missing")).await;
+    assert_eq!(res.status, StatusCode::NotFound);
+}
+
+#[tokio::test]
+async fn extension_methods_reach_the_router() {
+    let res = routes().handle(make_request(Method::Other("PROPFIND".to_string()), "/missing")).await;
+    assert_eq!(res.status, StatusCode::NotFound);
+}
+
+#[tokio::test]
+async fn unregistered_method_on_known_path_is_not_allowed() {
+    let res = routes().handle(make_request(Method::PUT, "/page")).await;
+    assert_eq!(res.status, StatusCode::MethodNotAllowed);
+    assert_eq!(res.headers.get("allow").unwrap(), "GET, HEAD, OPTIONS");
+}
+
+#[tokio::test]
+async fn not_allowed_lists_methods_of_every_matching_pattern() {
+    let router = Router::new()
+        .route(Method::GET, "/items/:id", echo_params)
+        .route(Method::DELETE, "/items/special", echo_params);
+
+    let res = router.handle(make_request(Method::POST, "/items/special")).await;
+    assert_eq!(res.status, StatusCode::MethodNotAllowed);
+    assert_eq!(res.headers.get("allow").unwrap(), "GET, HEAD, DELETE, OPTIONS");
+
+    let res = router.handle(make_request(Method::DELETE, "/items/3")).await;
+    assert_eq!(res.headers.get("allow").unwrap(), "GET, HEAD, OPTIONS");
+}
+
+#[tokio::test]
+async fn route_can_define_its_own_options_and_head() {
+    let router = Router::new()
+        .route(Method::GET, "/custom", echo_params)
+        .route(Method::OPTIONS, "/custom", |_req: Request| async {
+            Response::new().status(StatusCode::NoContent).header("X-Custom", "options")
+        })
+        .route(Method::HEAD, "/custom", |_req: Request| async {
+            Response::new().status(StatusCode::Ok).header("X-Custom", "head")
+        });
+
+    let res = router.handle(make_request(Method::OPTIONS, "/custom")).await;
+    assert_eq!(res.status, StatusCode::NoContent);
+    assert_eq!(res.headers.get("x-custom").unwrap(), "options");
+
+    let res = router.handle(make_request(Method::HEAD, "/custom")).await;
+    assert_eq!(res.headers.get("x-custom").unwrap(), "head");
+}
+
+#[tokio::test]
+async fn options_star_lists_every_method() {
+    let res = routes().handle(make_request(Method::OPTIONS, "*")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+    assert_eq!(res.headers.get("allow").unwrap(), "GET, HEAD, POST, OPTIONS");
+}
+
+async fn handler_body(res: Response) -> String {
+    String::from_utf8(res.body.collect().await.unwrap()).unwrap()
+}
+
+async fn echo_params(req: Request) -> Response {
+    let mut params: Vec<_> = req.params.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    params.sort();
+    Response::new().status(StatusCode::Ok).text(&params.join("&"))
+}
+
+#[tokio::test]
+async fn captures_named_params() {
+    let router = Router::new().route(Method::GET, "/users/:id/posts/:post", echo_params);
+
+    let res = router.handle(make_request(Method::GET, "/users/42/posts/7")).await;
+    assert_eq!(handler_body(res).await, "id=42&post=7");
+
+    let res = router.handle(make_request(Method::GET, "/users/42")).await;
+    assert_eq!(res.status, StatusCode::NotFound);
+}
+
+#[tokio::test]
+async fn params_parse_into_types() {
+    let router = Router::new().route(Method::GET, "/users/:id", |req: Request| async move {
+        match req.param::<u32>("id") {
+            Some(id) => Response::new().status(StatusCode::Ok).text(&(id + 1).to_string()),
+            None => Response::new().status(StatusCode::BadRequest),
+        }
+    });
+
+    let res = router.handle(make_request(Method::GET, "/users/41")).await;
+    assert_eq!(handler_body(res).await, "42");
+
+    let res = router.handle(make_request(Method::GET, "/users/abc")).await;
+    assert_eq!(res.status, StatusCode::BadRequest);
+}
+
+#[tokio::test]
+async fn params_are_percent_decoded() {
+    let router = Router::new()
+        .route(Method::GET, "/users/:name", echo_params)
+        .route(Method::GET, "/files/*path", echo_params);
+
+    let res = router.handle(make_request(Method::GET, "/users/john%20doe")).await;
+    assert_eq!(handler_body(res).await, "name=john doe");
+
+    let res = router.handle(make_request(Method::GET, "/files/a%20b/c%2Fd")).await;
+    assert_eq!(handler_body(res).await, "path=a b/c/d");
+
+    let res = router.handle(make_request(Method::GET, "/users/Jos%C3%A9")).await;
+    assert_eq!(handler_body(res).await, "name=José");
+
+    let res = router.handle(make_request(Method::GET, "/users/bad%zz")).await;
+    assert_eq!(res.status, StatusCode::NotFound);
+
+    let res = router.handle(make_request(Method::GET, "/users/bad%FF")).await;
+    assert_eq!(res.status, StatusCode::NotFound);
+}
+
+#[tokio::test]
+async fn wildcard_captures_the_rest_of_the_path() {
+    let router = Router::new().route(Method::GET, "/static/*path", echo_params);
+
+    let res = router.handle(make_request(Method::GET, "/static/css/site.css")).await;
+    assert_eq!(handler_body(res).await, "path=css/site.css");
+}
+
+#[tokio::test]
+async fn static_beats_param_beats_wildcard() {
+    let tagged = |tag: &'static str| move |_req: Request| async move {
+        Response::new().status(StatusCode::Ok).text(&tag)
+    };
+    let router = Router::new()
+        .route(Method::GET, "/files/*rest", tagged("wildcard"))
+        .route(Method::GET, "/files/:name", tagged("param"))
+        .route(Method::GET, "/files/readme", tagged("static"));
+
+    let res = router.handle(make_request(Method::GET, "/files/readme")).await;
+    assert_eq!(handler_body(res).await, "static");
+    let res = router.handle(make_request(Method::GET, "/files/other")).await;
+    assert_eq!(handler_body(res).await, "param");
+    let res = router.handle(make_request(Method::GET, "/files/a/b")).await;
+    assert_eq!(handler_body(res).await, "wildcard");
+}
+
+#[tokio::test]
+async fn allowed_methods_come_from_registered_routes() {
+    let router = Router::new()
+        .route(Method::GET, "/items/:id", echo_params)
+        .route(Method::PATCH, "/items/:id", echo_params);
+
+    assert_eq!(router.allowed_methods("/items/3"), [Method::GET, Method::HEAD, Method::PATCH, Method::OPTIONS]);
+    assert!(router.allowed_methods("/other").is_empty());
+}
+
+#[tokio::test]
+async fn handlers_take_extractors() {
+    use crate::core::extract::{Path, State};
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct ItemPath {
+        id: u32,
+    }
+
+    async fn show_item(Path(path): Path<ItemPath>, State(prefix): State<String>) -> Response {
+        Response::new().status(StatusCode::Ok).text(&format!("{}{}", prefix, path.id))
+    }
+
+    let router = Router::new()
+        .with_state("item-".to_string())
+        .route(Method::GET, "/items/:id", show_item);
+
+    let res = router.handle(make_request(Method::GET, "/items/7")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+    assert_eq!(res.body.as_bytes().unwrap(), b"item-7");
+
+    let res = router.handle(make_request(Method::GET, "/items/seven")).await;
+    assert_eq!(res.status, StatusCode::BadRequest);
+}
+
+#[tokio::test]
+async fn nested_routes_are_mounted_under_the_prefix() {
+    let api = Router::new()
+        .route(Method::GET, "/", |_req: Request| async { Response::new().status(StatusCode::Ok).text(&"api root") })
+        .route(Method::GET, "/users/:id", echo_params);
+    let router = Router::new().nest("/api/v1", api);
+
+    let res = router.handle(make_request(Method::GET, "/api/v1")).await;
+    assert_eq!(res.body.as_bytes().unwrap(), b"api root");
+
+    let res = router.handle(make_request(Method::GET, "/api/v1/users/5")).await;
+    assert_eq!(res.body.as_bytes().unwrap(), b"id=5");
+
+    let res = router.handle(make_request(Method::GET, "/users/5")).await;
+    assert_eq!(res.status, StatusCode::NotFound);
+}
+
+#[tokio::test]
+async fn group_middleware_only_wraps_its_own_routes() {
+    use crate::http::middleware::auth::Auth;
+
+    let admin = Router::new()
+        .route(Method::GET, "/stats", echo_params)
+        .layer(Auth);
+    let router = routes().nest("/admin", admin);
+
+    let res = router.handle(make_request(Method::GET, "/admin/stats")).await;
+    assert_eq!(res.status, StatusCode::Unauthorized);
+
+    let res = router.handle(make_request(Method::GET, "/")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+
+    // Unmatched methods never reach the group's middleware
+    let res = router.handle(make_request(Method::POST, "/admin/stats")).await;
+    assert_eq!(res.status, StatusCode::MethodNotAllowed);
+
+    let mut req = make_request(Method::GET, "/admin/stats");
+    req.headers.insert("Authorization", "Bearer token");
+    let res = router.handle(req).await;
+    assert_eq!(res.status, StatusCode::Ok);
+}
+
+#[tokio::test]
+async fn outer_layers_wrap_nested_routes_too() {
+    use crate::http::middleware::add_header::AddHeader;
+
+    let inner = Router::new().route(Method::GET, "/ping", echo_params);
+    let router = Router::new().nest("/inner", inner).layer(AddHeader);
+
+    let res = router.handle(make_request(Method::GET, "/inner/ping")).await;
+    assert!(res.headers.contains_key("x-example"));
+}
+
+#[tokio::test]
+async fn nested_router_keeps_its_state() {
+    use crate::core::extract::State;
+
+    async fn name(State(name): State<&'static str>) -> Response {
+        Response::new().status(StatusCode::Ok).text(&name.as_bytes())
+    }
+
+    let router = Router::new()
+        .with_state("outer")
+        .route(Method::GET, "/", name)
+        .nest("/inner", Router::new().with_state("inner").route(Method::GET, "/", name));
+
+    let res = router.handle(make_request(Method::GET, "/")).await;
+    assert_eq!(res.body.as_bytes().unwrap(), b"outer");
+    let res = router.handle(make_request(Method::GET, "/inner")).await;
+    assert_eq!(res.body.as_bytes().unwrap(), b"inner");
+}
@@ -1,7 +1,7 @@
 use std::{collections::HashMap, fs::{self}, path::PathBuf};
 use serde_json::json;
 
-use crate::core::{parser::Request, response::{Response, StatusCode}};
+use crate::core::{body::Body, parser::Request, response::{Response, StatusCode}};
 
 pub async fn serve_file(req: &Request, res: Response) -> Response {
     let path_split: Vec<&str> = req.path.split('/').collect();
@@ -11,24 +11,23 @@ pub async fn serve_file(req: &Request, res: Response) -> Response {
     let metadata = match tokio::fs::metadata(&rel_path).await {
         Ok(m) => m,
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => return res.status(StatusCode::NotFound).text(&"404 Not Found"),
-        Err(_) => return res.status(StatusCode::InternalError).text(&"500 Internal Error")
+        Err(_) => return res.status(StatusCode::InternalError).text(&"500 Internal Server Error")
     };
 
     if metadata.is_file() {
-        return match tokio::fs::read(&rel_path).await {
-            Ok(bytes) => {
+        // The file is streamed from disk by the connection rather than read into memory here
+        return match tokio::fs::File::open(&rel_path).await {
+            Ok(file) => {
                 let mime_type = get_mime_type(file_name);
-                let mut file_res = res.status(StatusCode::Ok).text(&"")
+                res.status(StatusCode::Ok)
                     .header("Content-Type", mime_type)
-                    .header("Content-Length", bytes.len().to_string().as_str());
-                file_res.body = bytes;
-                file_res
+                    .body(Body::File { file, offset: 0, len: metadata.len() })
             }
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
                 res.status(StatusCode::NotFound).text(&"404 Not Found")
             }
             Err(_) => {
-                res.status(StatusCode::InternalError).text(&"500 Internal Error")
+                res.status(StatusCode::InternalError).text(&"500 Internal Server Error")
             }
         }
     }
@@ -45,7 +44,7 @@ pub async fn serve_file(req: &Request, res: Response) -> Response {
         }
         return res.status(StatusCode::Ok).json(&json!(&vec));
     }
-    res.status(StatusCode::InternalError).text(&"500 Internal Error")
+    res.status(StatusCode::InternalError).text(&"500 Internal Server Error")
 
 }
 
@@ -58,19 +57,10 @@ fn get_mime_type(file_name: &str) -> &str {
     mime_types.insert("txt", "text/plain");
 
     let file_extension = file_name.rsplit_once('.').map(|(_, ext)| ext);
-    return match file_extension {
-        Some(ext) => {
-            match mime_types.get(ext) {
-                Some(mime) => {
-                    *mime
-                }
-                None => {
-                    &"application/octet-stream"
-                }
-            }
-        }
+    match file_extension {
+        Some(ext) => mime_types.get(ext).unwrap_or(&"application/octet-stream"),
         None => {
-            &""
+            ""
         }
     }
 }
@@ -1 +1,2 @@
 pub mod file_serving;
+pub mod redirect;
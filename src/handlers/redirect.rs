@@ -0,0 +1,46 @@
+use crate::core::{parser::Request, response::{Response, StatusCode}};
+
+// Sends the client to the same host and path over HTTPS. The 308 keeps the
+// method and body, so a POST to http:// is repeated as a POST. The port is
+// left out of the URL when it's 443.
+pub fn https_redirect(req: &Request, https_port: u16) -> Response {
+    let Some(host) = req.headers.get("Host").and_then(host_name) else {
+        return Response::new().status(StatusCode::BadRequest).text(&"400 Bad Request");
+    };
+    // Only origin-form ("/path?query") can be appended to the host
+    if !req.target.starts_with('/') {
+        return Response::new().status(StatusCode::BadRequest).text(&"400 Bad Request");
+    };
+
+    let mut location = format!("https://{}", host);
+    if https_port != 443 {
+        location += &format!(":{}", https_port);
+    }
+    // The raw target keeps the query as sent: order, repeated keys and valueless flags
+    location += &req.target;
+
+    // The request line can't carry CR or LF, but handlers can build requests by hand
+    let mut res = Response::new();
+    if res.try_header("Location", &location).is_err() {
+        return Response::new().status(StatusCode::BadRequest).text(&"400 Bad Request");
+    }
+    res.status(StatusCode::PermanentRedirect).text(&"308 Permanent Redirect")
+}
+
+// The Host header without its port. None if it isn't a plausible host,
+// so the Location can't be pointed somewhere else.
+fn host_name(host: &str) -> Option<&str> {
+    let host = host.trim();
+    let name = if host.starts_with('[') {
+        &host[..=host.find(']')?]
+    } else {
+        host.split(':').next()?
+    };
+
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || "-.[]:".contains(c));
+    valid.then_some(name)
+}
+
+#[cfg(test)]
+#[path ="tests/redirect.rs"]
+mod redirect_tests;
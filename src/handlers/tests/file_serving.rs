@@ -43,7 +43,8 @@ fn make_request(path: &str) -> Request {
         body: vec![],
         version: "HTTP 1.1".to_string(),
         query: HashMap::new(),
-        cookies: None
+        cookies: None,
+        ..Default::default()
     }
 }
 
@@ -54,8 +55,9 @@ async fn serves_existing_file() {
 
     let req = make_request("/static/test.txt");
     let res = serve_file(&req, Response::new()).await;
-    let body = String::from_utf8_lossy(&res.body);
-    
+    assert_eq!(res.body.len(), Some(5));
+    let body = String::from_utf8(res.body.collect().await.unwrap()).unwrap();
+
     assert_eq!(res.status, StatusCode::Ok);
     assert_eq!(body, "hello");
     assert!(res.headers.get("content-type").unwrap().contains("text/plain"));
@@ -80,7 +82,7 @@ async fn serves_directory_listing_as_json() {
     let res = serve_file(&req, Response::new()).await;
     assert_eq!(res.status, StatusCode::Ok);
 
-    let body = String::from_utf8_lossy(&res.body);
+    let body = String::from_utf8_lossy(res.body.as_bytes().unwrap());
     assert!(body.contains("file1.txt"));
     assert!(body.contains("file2.txt"));
 
@@ -0,0 +1,51 @@
+use super::*;
+
+fn request(host: Option<&str>, target: &str) -> Request {
+    let path = target.split('?').next().unwrap_or_default().to_string();
+    let mut req = Request { path, target: target.to_string(), ..Default::default() };
+    if let Some(host) = host {
+        req.headers.insert("Host", host);
+    }
+    req
+}
+
+#[test]
+fn redirects_to_the_same_host_and_path() {
+    let res = https_redirect(&request(Some("example.com:80"), "/docs/intro"), 443);
+    assert_eq!(res.status, StatusCode::PermanentRedirect);
+    assert_eq!(res.headers.get("location").unwrap(), "https://example.com/docs/intro");
+}
+
+#[test]
+fn keeps_the_query_and_a_non_default_port() {
+    let res = https_redirect(&request(Some("localhost:8080"), "/search?q=rust"), 8443);
+    assert_eq!(res.headers.get("location").unwrap(), "https://localhost:8443/search?q=rust");
+
+    let res = https_redirect(&request(Some("[::1]:8080"), "/"), 443);
+    assert_eq!(res.headers.get("location").unwrap(), "https://[::1]/");
+}
+
+#[test]
+fn query_is_passed_through_as_sent() {
+    let res = https_redirect(&request(Some("example.com"), "/a%20b?tag=x&tag=y&flag&z=%2F"), 443);
+    assert_eq!(res.headers.get("location").unwrap(), "https://example.com/a%20b?tag=x&tag=y&flag&z=%2F");
+}
+
+#[test]
+fn non_origin_form_target_is_bad_request() {
+    assert_eq!(https_redirect(&request(Some("example.com"), "http://evil.com/"), 443).status, StatusCode::BadRequest);
+    assert_eq!(https_redirect(&request(Some("example.com"), "*"), 443).status, StatusCode::BadRequest);
+}
+
+#[test]
+fn missing_or_odd_host_is_bad_request() {
+    assert_eq!(https_redirect(&request(None, "/"), 443).status, StatusCode::BadRequest);
+    assert_eq!(https_redirect(&request(Some("evil.com/x@"), "/"), 443).status, StatusCode::BadRequest);
+}
+
+#[test]
+fn path_that_would_split_the_location_is_bad_request() {
+    let res = https_redirect(&request(Some("example.com"), "/a\r\nSet-Cookie: x=1"), 443);
+    assert_eq!(res.status, StatusCode::BadRequest);
+    assert!(!res.headers.contains_key("location"));
+}
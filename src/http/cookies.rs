@@ -25,7 +25,7 @@ impl Cookie {
     }
 
     pub fn serialize(&self) -> String {
-        let mut result = String::from(format!("{}={}; Path={}; Expires={};", self.name, self.value, self.path, self.expires));
+        let mut result = format!("{}={}; Path={}; Expires={};", self.name, self.value, self.path, self.expires);
         if self.http_only { result.push_str(" HttpOnly;"); }
         if self.secure { result.push_str(" Secure;"); }
 
@@ -4,6 +4,7 @@ use crate::core::{parser::Request, response::Response};
 
 use super::{Middleware, Next};
 
+#[allow(dead_code)]
 pub struct AddHeader;
 #[async_trait]
 impl Middleware for AddHeader {
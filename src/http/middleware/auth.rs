@@ -4,12 +4,13 @@ use crate::core::{parser::Request, response::{Response, StatusCode}};
 
 use super::{Middleware, Next};
 
+#[allow(dead_code)]
 pub struct Auth;
 #[async_trait]
 impl Middleware for Auth {
     async fn handle(&self, req: Request, next: Next) -> Response {
-        if req.headers.get("Authorization").is_none() {
-            return Response::new().status(StatusCode::Unauthorized);
+        if !req.headers.contains_key("Authorization") {
+            return Response::new().status(StatusCode::Unauthorized).text(&"401 Unauthorized");
         }
 
         let res = next(req).await;
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::core::{parser::{Method, Request}, response::Response};
+
+use super::{Middleware, Next};
+
+// Decides per request whether a middleware runs. Conditions combine with
+// `and`, `or` and `not`, e.g. `Condition::path_prefix("/static").not()`.
+#[derive(Clone)]
+pub struct Condition(Arc<dyn Fn(&Request) -> bool + Send + Sync>);
+
+impl Condition {
+    pub fn predicate(f: impl Fn(&Request) -> bool + Send + Sync + 'static) -> Condition {
+        Condition(Arc::new(f))
+    }
+
+    // Matches whole segments: "/admin" covers "/admin" and "/admin/users", not "/administrator"
+    pub fn path_prefix(prefix: &str) -> Condition {
+        let prefix = prefix.trim_end_matches('/').to_string();
+        Condition::predicate(move |req| match req.path.strip_prefix(prefix.as_str()) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn method(method: Method) -> Condition {
+        Condition::predicate(move |req| req.method == method)
+    }
+
+    // Compared against the Host header, ignoring case and any port
+    #[allow(dead_code)]
+    pub fn host(host: &str) -> Condition {
+        let host = host.to_string();
+        Condition::predicate(move |req| {
+            let value = req.headers.get("Host").unwrap_or("");
+            let name = match value.rsplit_once(':') {
+                Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+                _ => value,
+            };
+            name.eq_ignore_ascii_case(&host)
+        })
+    }
+
+    pub fn not(self) -> Condition {
+        Condition::predicate(move |req| !self.matches(req))
+    }
+
+    #[allow(dead_code)]
+    pub fn and(self, other: Condition) -> Condition {
+        Condition::predicate(move |req| self.matches(req) && other.matches(req))
+    }
+
+    #[allow(dead_code)]
+    pub fn or(self, other: Condition) -> Condition {
+        Condition::predicate(move |req| self.matches(req) || other.matches(req))
+    }
+
+    pub fn matches(&self, req: &Request) -> bool {
+        (self.0)(req)
+    }
+}
+
+// Runs the wrapped middleware only when the condition holds; other requests go straight to `next`
+pub struct Conditional {
+    condition: Condition,
+    middleware: Arc<dyn Middleware>,
+}
+
+impl Conditional {
+    pub fn new(condition: Condition, middleware: impl Middleware + 'static) -> Conditional {
+        Conditional { condition, middleware: Arc::new(middleware) }
+    }
+}
+
+#[async_trait]
+impl Middleware for Conditional {
+    async fn handle(&self, req: Request, next: Next) -> Response {
+        if self.condition.matches(&req) {
+            self.middleware.handle(req, next).await
+        } else {
+            next(req).await
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+
+use crate::core::{parser::{Method, Request}, response::{Response, StatusCode}};
+
+use super::{Middleware, Next};
+
+#[allow(dead_code)]
+#[derive(Clone)]
+enum AllowOrigin {
+    Any,
+    // Exact origins, or patterns with a single `*` such as "https://*.example.com"
+    List(Vec<String>),
+    Predicate(Arc<dyn Fn(&str) -> bool + Send + Sync>),
+}
+
+// Cross-origin resource sharing. Preflight requests (OPTIONS with an
+// Access-Control-Request-Method) are answered here and never reach the handler;
+// other requests from an allowed origin get the Access-Control-* headers added.
+//
+//     Cors::new()
+//         .allow_origin("https://app.example.com")
+//         .allow_methods([Method::GET, Method::POST])
+//         .allow_headers(["Content-Type"])
+//         .max_age(Duration::from_secs(600))
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct Cors {
+    origins: AllowOrigin,
+    methods: Vec<Method>,
+    headers: Vec<String>,
+    any_header: bool,
+    credentials: bool,
+    expose: Vec<String>,
+    max_age: Option<Duration>,
+}
+
+#[allow(dead_code)]
+impl Cors {
+    // Allows no origins until some are added; methods default to GET, HEAD and POST
+    pub fn new() -> Cors {
+        Cors {
+            origins: AllowOrigin::List(vec![]),
+            methods: vec![Method::GET, Method::HEAD, Method::POST],
+            headers: vec![],
+            any_header: false,
+            credentials: false,
+            expose: vec![],
+            max_age: None,
+        }
+    }
+
+    pub fn allow_origin(mut self, origin: &str) -> Cors {
+        match &mut self.origins {
+            AllowOrigin::List(list) => list.push(origin.to_string()),
+            other => *other = AllowOrigin::List(vec![origin.to_string()]),
+        }
+        self
+    }
+
+    pub fn allow_any_origin(mut self) -> Cors {
+        self.origins = AllowOrigin::Any;
+        self
+    }
+
+    pub fn allow_origin_fn(mut self, f: impl Fn(&str) -> bool + Send + Sync + 'static) -> Cors {
+        self.origins = AllowOrigin::Predicate(Arc::new(f));
+        self
+    }
+
+    pub fn allow_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Cors {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    pub fn allow_headers<'a>(mut self, headers: impl IntoIterator<Item = &'a str>) -> Cors {
+        self.headers = headers.into_iter().map(str::to_string).collect();
+        self
+    }
+
+    // Allows whatever headers the preflight asks for
+    pub fn allow_any_header(mut self) -> Cors {
+        self.any_header = true;
+        self
+    }
+
+    pub fn allow_credentials(mut self, allow: bool) -> Cors {
+        self.credentials = allow;
+        self
+    }
+
+    pub fn expose_headers<'a>(mut self, headers: impl IntoIterator<Item = &'a str>) -> Cors {
+        self.expose = headers.into_iter().map(str::to_string).collect();
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Cors {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn origin_allowed(&self, origin: &str) -> bool {
+        match &self.origins {
+            AllowOrigin::Any => true,
+            AllowOrigin::List(list) => list.iter().any(|allowed| origin_matches(allowed, origin)),
+            AllowOrigin::Predicate(f) => f(origin),
+        }
+    }
+
+    // "*" can't be combined with credentials, so the origin is echoed back instead
+    fn allow_origin_value<'a>(&self, origin: &'a str) -> &'a str {
+        match self.origins {
+            AllowOrigin::Any if !self.credentials => "*",
+            _ => origin,
+        }
+    }
+
+    // Whether the Allow-Origin header depends on the request's Origin
+    fn varies_by_origin(&self) -> bool {
+        self.allow_origin_value("") != "*"
+    }
+
+    fn preflight(&self, req: &Request, origin: &str) -> Response {
+        let mut res = Response::new().status(StatusCode::NoContent);
+        res = add_vary(res, &["Origin", "Access-Control-Request-Method", "Access-Control-Request-Headers"]);
+
+        let method_allowed = req.headers.get("Access-Control-Request-Method")
+            .is_some_and(|m| self.methods.iter().any(|allowed| allowed.as_str() == m.trim()));
+        if !self.origin_allowed(origin) || !method_allowed {
+            return res;
+        }
+
+        let methods = self.methods.iter().map(Method::as_str).collect::<Vec<_>>().join(", ");
+        res = res.header("Access-Control-Allow-Origin", self.allow_origin_value(origin))
+            .header("Access-Control-Allow-Methods", &methods);
+
+        let headers = if self.any_header {
+            req.headers.get("Access-Control-Request-Headers").unwrap_or("").to_string()
+        } else {
+            self.headers.join(", ")
+        };
+        if !headers.is_empty() {
+            res = res.header("Access-Control-Allow-Headers", &headers);
+        }
+        if self.credentials {
+            res = res.header("Access-Control-Allow-Credentials", "true");
+        }
+        if let Some(max_age) = self.max_age {
+            res = res.header("Access-Control-Max-Age", &max_age.as_secs().to_string());
+        }
+        res
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Cors {
+        Cors::new()
+    }
+}
+
+#[async_trait]
+impl Middleware for Cors {
+    async fn handle(&self, req: Request, next: Next) -> Response {
+        let origin = match req.headers.get("Origin") {
+            Some(origin) => origin.to_string(),
+            None => {
+                let res = next(req).await;
+                return if self.varies_by_origin() { add_vary(res, &["Origin"]) } else { res };
+            }
+        };
+
+        if req.method == Method::OPTIONS && req.headers.contains_key("Access-Control-Request-Method") {
+            return self.preflight(&req, &origin);
+        }
+
+        let mut res = next(req).await;
+        if self.varies_by_origin() {
+            res = add_vary(res, &["Origin"]);
+        }
+        if !self.origin_allowed(&origin) {
+            return res;
+        }
+
+        res = res.header("Access-Control-Allow-Origin", self.allow_origin_value(&origin));
+        if self.credentials {
+            res = res.header("Access-Control-Allow-Credentials", "true");
+        }
+        if !self.expose.is_empty() {
+            res = res.header("Access-Control-Expose-Headers", &self.expose.join(", "));
+        }
+        res
+    }
+}
+
+#[allow(dead_code)]
+fn origin_matches(allowed: &str, origin: &str) -> bool {
+    match allowed.split_once('*') {
+        Some((start, end)) => {
+            origin.len() > start.len() + end.len() && origin.starts_with(start) && origin.ends_with(end)
+        }
+        None => allowed.eq_ignore_ascii_case(origin),
+    }
+}
+
+// Adds names to the Vary header, keeping whatever the handler already put there
+#[allow(dead_code)]
+fn add_vary(res: Response, names: &[&str]) -> Response {
+    let existing = res.headers.get_all("Vary")
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>();
+    if existing.iter().any(|v| v == "*") {
+        return res;
+    }
+
+    let mut vary = existing.clone();
+    for name in names {
+        if !vary.iter().any(|v| v.eq_ignore_ascii_case(name)) {
+            vary.push(name.to_string());
+        }
+    }
+    if vary == existing {
+        return res;
+    }
+    res.header("Vary", &vary.join(", "))
+}
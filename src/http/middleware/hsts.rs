@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::core::{parser::Request, response::Response};
+
+use super::{Middleware, Next};
+
+// Adds Strict-Transport-Security to responses sent over TLS. Browsers ignore
+// it over cleartext, so plain connections (e.g. the redirect listener) are left alone.
+pub struct Hsts {
+    max_age: Duration,
+    include_subdomains: bool,
+    preload: bool,
+}
+
+impl Hsts {
+    pub fn new(max_age: Duration) -> Hsts {
+        Hsts { max_age, include_subdomains: false, preload: false }
+    }
+
+    #[allow(dead_code)]
+    pub fn include_subdomains(mut self) -> Hsts {
+        self.include_subdomains = true;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn preload(mut self) -> Hsts {
+        self.preload = true;
+        self
+    }
+
+    fn value(&self) -> String {
+        let mut value = format!("max-age={}", self.max_age.as_secs());
+        if self.include_subdomains {
+            value += "; includeSubDomains";
+        }
+        if self.preload {
+            value += "; preload";
+        }
+        value
+    }
+}
+
+#[async_trait]
+impl Middleware for Hsts {
+    async fn handle(&self, req: Request, next: Next) -> Response {
+        let is_tls = req.connection.is_tls();
+        let res = next(req).await;
+        if !is_tls || res.headers.contains_key("Strict-Transport-Security") {
+            return res;
+        }
+        res.header("Strict-Transport-Security", &self.value())
+    }
+}
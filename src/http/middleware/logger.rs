@@ -22,11 +22,23 @@ impl Middleware for Logger {
             method  = ?req.method,
             path    = %req.path,
             query   = ?req.query,
+            peer    = ?req.connection.peer_addr,
             headers = formatted_headers,
             "request_received"
         );
 
-        next(req).await
+        let method = req.method.clone();
+        let path = req.path.clone();
+        let res = next(req).await;
+
+        tracing::info!(
+            method  = ?method,
+            path    = %path,
+            status  = res.status.as_u16(),
+            "response_sent"
+        );
+
+        res
     }
 }
 
@@ -62,43 +74,41 @@ fn compress_old_logs() {
         }
     };
 
-    for entry_result in entries {
-        if let Ok(entry) = entry_result {
-            let file_name = entry.file_name();
-            let fn_str = file_name.to_string_lossy();
-            let path = Path::new("logs").join(fn_str.as_ref());
-
-            if fn_str.ends_with(".gz") {
-                if let Some(date_part) = fn_str.strip_prefix("server.log.").and_then(|s| s.strip_suffix(".gz")) {
-                    if let Ok(log_date) = NaiveDate::parse_from_str(date_part, "%Y-%m-%d") {
-                        if today.signed_duration_since(log_date) > chrono::Duration::days(30) {
-                            match std::fs::remove_file(&path) {
-                                Ok(_) => println!("Deleted old log: {:?}", path),
-                                Err(_) => println!("Could not delete old log: {:?}", path),
-                            }
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let fn_str = file_name.to_string_lossy();
+        let path = Path::new("logs").join(fn_str.as_ref());
+
+        if fn_str.ends_with(".gz") {
+            if let Some(date_part) = fn_str.strip_prefix("server.log.").and_then(|s| s.strip_suffix(".gz")) {
+                if let Ok(log_date) = NaiveDate::parse_from_str(date_part, "%Y-%m-%d") {
+                    if today.signed_duration_since(log_date) > chrono::Duration::days(30) {
+                        match std::fs::remove_file(&path) {
+                            Ok(_) => println!("Deleted old log: {:?}", path),
+                            Err(_) => println!("Could not delete old log: {:?}", path),
                         }
                     }
                 }
-
-                continue;
             }
 
-            if let Some(date_part) = fn_str.strip_prefix("server.log.") {
-                if let Ok(log_date) = chrono::NaiveDate::parse_from_str(date_part, "%Y-%m-%d") {
-                    if log_date == today {
-                        continue;
-                    }
+            continue;
+        }
+
+        if let Some(date_part) = fn_str.strip_prefix("server.log.") {
+            if let Ok(log_date) = chrono::NaiveDate::parse_from_str(date_part, "%Y-%m-%d") {
+                if log_date == today {
+                    continue;
                 }
             }
+        }
 
 
-            match compress_file(&path) {
-                Ok(_) => {
-                    println!("Compressed: {:?}", path);
-                },
-                Err(_) => {
-                    eprintln!("Error compressing file: {:?}", path);
-                }
+        match compress_file(&path) {
+            Ok(_) => {
+                println!("Compressed: {:?}", path);
+            },
+            Err(_) => {
+                eprintln!("Error compressing file: {:?}", path);
             }
         }
     }
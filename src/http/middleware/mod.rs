@@ -1,6 +1,10 @@
 pub mod add_header;
 pub mod auth;
+pub mod conditional;
+pub mod cors;
+pub mod hsts;
 pub mod logger;
+pub mod rate_limit;
 pub mod timer;
 pub mod set_cookie;
 pub mod session_tracker;
@@ -8,49 +12,89 @@ pub mod session_tracker;
 use std::{future::Future, pin::Pin, sync::Arc };
 
 use async_trait::async_trait;
+use conditional::{Condition, Conditional};
 
-use crate::core::{parser::Request, response::Response};
+use crate::core::{extensions::Extensions, parser::Request, response::Response, router::Router};
 
+// Runs requests through the middleware chain, with the handler (normally the
+// router) as the innermost step. A middleware that returns without calling
+// `next` short-circuits: its response is the one sent, and the handler never runs.
 #[derive(Clone)]
 pub struct Dispatcher {
     middleware: Vec<Arc<dyn Middleware>>,
+    handler: Next,
+    state: Extensions,
 }
 
 impl Dispatcher {
     pub fn new() -> Dispatcher {
         Dispatcher {
-            middleware: vec![]
+            middleware: vec![],
+            handler: Arc::new(|_|
+                Box::pin(async move {
+                    Response::new()
+                })
+            ),
+            state: Extensions::new(),
         }
     }
 
+    // App state (a database pool, config, a cache...) shared by every request.
+    // Middleware finds it in `req.extensions`, handlers take a `State<T>` argument.
+    #[allow(dead_code)]
+    pub fn add_state<T: Send + Sync + 'static>(&mut self, state: T) {
+        self.state.insert(state);
+    }
+
     pub fn add(&mut self, mw: impl Middleware + 'static) {
         self.middleware.push(Arc::new(mw));
     }
 
-    pub async fn dispatch(&self, req: Request) -> Response {
-        let handler: Next = Arc::new(|_|
-            Box::pin(async move {
-                Response::new()
-            })
-        );
+    // Like `add`, but the middleware is skipped for requests the condition doesn't match
+    pub fn add_when(&mut self, condition: Condition, mw: impl Middleware + 'static) {
+        self.add(Conditional::new(condition, mw));
+    }
 
-        let composed: Next = self.middleware
-            .iter()
-            .rev()
-            .fold(handler, |next, mw| {
-                let mw = Arc::clone(mw);
-                Arc::new(move |req: Request| {
-                    let next = Arc::clone(&next);
-                    let cloned_middleware = mw.clone();
-                    Box::pin(async move {
-                        cloned_middleware.handle(req, next).await
-                    })
-                })
-            });
+    pub fn set_handler<F, Fut>(&mut self, handler: F)
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Response> + Send + 'static,
+    {
+        self.handler = Arc::new(move |req| Box::pin(handler(req)));
+    }
+
+    pub fn set_router(&mut self, router: Router) {
+        let router = Arc::new(router);
+        self.set_handler(move |req| {
+            let router = Arc::clone(&router);
+            async move { router.handle(req).await }
+        });
+    }
+
+    pub async fn dispatch(&self, mut req: Request) -> Response {
+        req.extensions.extend(&self.state);
+        let composed = compose(&self.middleware, Arc::clone(&self.handler));
         composed(req).await
     }
 }
 
+// Wraps the handler in the middleware, the first one added being the outermost
+pub(crate) fn compose(middleware: &[Arc<dyn Middleware>], handler: Next) -> Next {
+    middleware
+        .iter()
+        .rev()
+        .fold(handler, |next, mw| {
+            let mw = Arc::clone(mw);
+            Arc::new(move |req: Request| {
+                let next = Arc::clone(&next);
+                let cloned_middleware = mw.clone();
+                Box::pin(async move {
+                    cloned_middleware.handle(req, next).await
+                })
+            })
+        })
+}
+
 #[async_trait]
 pub trait Middleware: Send + Sync {
     async fn handle(
@@ -60,8 +104,8 @@ pub trait Middleware: Send + Sync {
     ) -> Response;
 }
 
-type Next = Arc<dyn Fn(Request) -> ResponseFuture + Send + Sync>;
-type ResponseFuture = Pin<Box<dyn Future<Output = Response> + Send>>;
+pub type Next = Arc<dyn Fn(Request) -> ResponseFuture + Send + Sync>;
+pub type ResponseFuture = Pin<Box<dyn Future<Output = Response> + Send>>;
 
 #[cfg(test)]
 #[path ="tests/middleware.rs"]
@@ -0,0 +1,150 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+use crate::core::{parser::Request, response::{Response, StatusCode}};
+
+use super::{session_tracker::Session, Middleware, Next};
+
+// How often take() drops buckets that have been idle long enough to be full again
+const EVICT_EVERY: Duration = Duration::from_secs(60);
+
+#[allow(dead_code)]
+type KeyFn = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+#[allow(dead_code)]
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    // When the bucket will be back to a full burst, after which it can be dropped
+    full_at: Instant,
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+struct Buckets {
+    map: HashMap<String, Bucket>,
+    next_eviction: Instant,
+}
+
+// Token bucket per client: each request takes a token, tokens come back at
+// `rate` per second up to `burst`. An empty bucket means 429 with Retry-After.
+// Every limited response carries RateLimit-Limit/Remaining/Reset.
+//
+// Clients are told apart by peer IP unless another key is chosen. Requests the
+// key can't be worked out for (no header, no session) aren't limited.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct RateLimit {
+    rate: f64,
+    burst: f64,
+    key: KeyFn,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+#[allow(dead_code)]
+impl RateLimit {
+    // `requests` per `per`, with a burst of the same size.
+    // Panics if either is zero, since nothing would ever refill the bucket.
+    pub fn new(requests: u32, per: Duration) -> RateLimit {
+        assert!(requests > 0, "RateLimit needs at least one request per period");
+        assert!(!per.is_zero(), "RateLimit needs a non-zero period");
+        RateLimit {
+            rate: requests as f64 / per.as_secs_f64(),
+            burst: requests as f64,
+            key: Arc::new(|req| req.connection.peer_addr.map(|addr| addr.ip().to_string())),
+            buckets: Arc::new(Mutex::new(Buckets { map: HashMap::new(), next_eviction: Instant::now() + EVICT_EVERY })),
+        }
+    }
+
+    // Panics if zero, as no request could ever get through
+    pub fn burst(mut self, burst: u32) -> RateLimit {
+        assert!(burst > 0, "RateLimit burst must be at least 1");
+        self.burst = burst as f64;
+        self
+    }
+
+    // e.g. an API key header
+    pub fn key_by_header(self, name: &str) -> RateLimit {
+        let name = name.to_string();
+        self.key_by(move |req| req.headers.get(&name).map(str::to_string))
+    }
+
+    // The session from SessionTracker, which has to run before this. Requests
+    // without a session cookie fall back to the peer IP, since they'd get a new
+    // session (and a fresh bucket) every time. Clients pick their own cookie
+    // values, so this suits per-user quotas rather than abuse protection.
+    pub fn key_by_session(self) -> RateLimit {
+        self.key_by(|req| match req.extensions.get::<Session>() {
+            Some(session) if !session.is_new => Some(format!("session:{}", session.id)),
+            _ => req.connection.peer_addr.map(|addr| addr.ip().to_string()),
+        })
+    }
+
+    pub fn key_by(mut self, f: impl Fn(&Request) -> Option<String> + Send + Sync + 'static) -> RateLimit {
+        self.key = Arc::new(f);
+        self
+    }
+
+    // Takes a token if there is one. Returns the tokens left, or how long until the next one.
+    fn take(&self, key: String) -> Result<f64, Duration> {
+        // A panic elsewhere while holding the lock leaves the buckets usable, so carry on with them
+        let mut buckets = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = Instant::now();
+        // A full bucket is the same as no bucket, so idle clients can be forgotten
+        if now >= buckets.next_eviction {
+            buckets.map.retain(|_, b| b.full_at > now);
+            buckets.next_eviction = now + EVICT_EVERY;
+        }
+        let bucket = buckets.map.entry(key).or_insert(Bucket { tokens: self.burst, last_refill: now, full_at: now });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.last_refill = now;
+
+        let result = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(bucket.tokens)
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        };
+        bucket.full_at = now + Duration::from_secs_f64((self.burst - bucket.tokens) / self.rate);
+        result
+    }
+
+    fn add_headers(&self, res: Response, remaining: f64) -> Response {
+        let reset = ((self.burst - remaining) / self.rate).ceil() as u64;
+        res.header("RateLimit-Limit", &(self.burst as u64).to_string())
+            .header("RateLimit-Remaining", &(remaining.floor() as u64).to_string())
+            .header("RateLimit-Reset", &reset.to_string())
+    }
+}
+
+#[async_trait]
+impl Middleware for RateLimit {
+    async fn handle(&self, req: Request, next: Next) -> Response {
+        let key = match (self.key)(&req) {
+            Some(key) => key,
+            None => return next(req).await,
+        };
+
+        match self.take(key) {
+            Ok(remaining) => {
+                let res = next(req).await;
+                self.add_headers(res, remaining)
+            }
+            Err(retry_after) => {
+                let res = Response::new()
+                    .status(StatusCode::TooManyRequests)
+                    .header("Retry-After", &(retry_after.as_secs_f64().ceil() as u64).to_string())
+                    .text(&"429 Too Many Requests");
+                self.add_headers(res, 0.0)
+            }
+        }
+    }
+}
@@ -14,30 +14,45 @@ pub struct SessionData {
     last_accessed: DateTime<Utc>
 }
 
+// Attached to each request's extensions by SessionTracker
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    pub id: String,
+    // True when the request had no session cookie and this id was just minted
+    pub is_new: bool,
+}
+
 #[derive(Clone, Debug)]
 pub struct SessionTracker {
     sessions: Arc<RwLock<HashMap<String, SessionData>>>
 }
 #[async_trait]
 impl Middleware for SessionTracker {
-    async fn handle(&self, req: Request, next: Next) -> Response {
-        let new_id = Uuid::new_v4().to_string();
-        let cookies_clone = req.cookies.clone();
-        if let Some(cookies) = cookies_clone {
-            if let Ok(mut sessions) = self.sessions.write() {
-                if let Some(session) = cookies.get("session_id") {
-                    if let Some(data) = sessions.get_mut(session) {
-                        data.last_accessed = Utc::now();
-                    }                 
-                } else {
-                    let mut new_session = SessionData { data: HashMap::new(), last_accessed: Utc::now() };
-                    new_session.data.insert("init".to_string(), "true".to_string());
-                    sessions.insert(new_id.clone(), new_session);
-                }
+    async fn handle(&self, mut req: Request, next: Next) -> Response {
+        let existing = req.cookies.as_ref()
+            .and_then(|cookies| cookies.get("session_id"))
+            .cloned();
+        let session = match existing {
+            Some(id) => Session { id, is_new: false },
+            None => Session { id: Uuid::new_v4().to_string(), is_new: true },
+        };
+        if let Ok(mut sessions) = self.sessions.write() {
+            if session.is_new {
+                let mut new_session = SessionData { data: HashMap::new(), last_accessed: Utc::now() };
+                new_session.data.insert("init".to_string(), "true".to_string());
+                sessions.insert(session.id.clone(), new_session);
+            } else if let Some(data) = sessions.get_mut(&session.id) {
+                data.last_accessed = Utc::now();
             }
         }
-        let res = next(req).await.header("Set-Cookie", format!("session_id={}", new_id).as_str());
-        res
+        req.extensions.insert(session.clone());
+        let res = next(req).await;
+        // Clients that already have a session keep it; only a new one is handed out
+        if session.is_new {
+            res.append_header("Set-Cookie", format!("session_id={}", session.id).as_str())
+        } else {
+            res
+        }
     }
 }
 
@@ -51,7 +66,7 @@ impl SessionTracker {
                 if let Ok(mut sessions) = session_threads.write() {
                     let time_limit = Duration::minutes(15);
                     let now = Utc::now();
-                    sessions.retain(|_, d| now - d.last_accessed > time_limit);
+                    sessions.retain(|_, d| now - d.last_accessed <= time_limit);
                 }
             }
         });
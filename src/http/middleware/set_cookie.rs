@@ -11,7 +11,7 @@ pub struct SetCookie;
 impl Middleware for SetCookie {
     async fn handle(&self, req: Request, next: Next) -> Response {
         let cookie = Cookie::new("session_id".to_string(), Uuid::new_v4().to_string());
-        let res = next(req).await.header("Set-Cookie", cookie.serialize().as_str());
+        let res = next(req).await.append_header("Set-Cookie", cookie.serialize().as_str());
         res
     }
 }
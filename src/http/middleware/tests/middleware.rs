@@ -28,6 +28,7 @@ async fn dispatch_returns_default_response_with_no_middleware() {
         version: "HTTP 1.1".to_string(),
         query: HashMap::new(),
         cookies: None,
+        ..Default::default()
     };
 
     let res = dispatcher.dispatch(req).await;
@@ -51,6 +52,7 @@ async fn single_middleware_is_invoked() {
         version: "HTTP 1.1".to_string(),
         query: HashMap::new(),
         cookies: None,
+        ..Default::default()
     };
 
     dispatcher.dispatch(req).await;
@@ -88,6 +90,7 @@ async fn middleware_is_invoked_in_reverse_order() {
         version: "HTTP 1.1".to_string(),
         query: HashMap::new(),
         cookies: None,
+        ..Default::default()
     };
 
     dispatcher.dispatch(req).await;
@@ -95,3 +98,396 @@ async fn middleware_is_invoked_in_reverse_order() {
     let logs = log.lock().unwrap();
     assert_eq!(&logs[..], ["First", "Second", "Third"]);
 }
+
+#[tokio::test]
+async fn cookie_middleware_do_not_overwrite_each_other() {
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add(set_cookie::SetCookie);
+    dispatcher.add(session_tracker::SessionTracker::new());
+
+    let req = Request {
+        method: Method::GET,
+        path: "/".to_string(),
+        headers: Default::default(),
+        body: vec![],
+        version: "HTTP 1.1".to_string(),
+        query: HashMap::new(),
+        cookies: Some(HashMap::new()),
+        ..Default::default()
+    };
+
+    let res = dispatcher.dispatch(req).await;
+    assert_eq!(res.headers.get_all("Set-Cookie").count(), 2);
+}
+
+fn make_request(path: &str) -> Request {
+    Request {
+        method: Method::GET,
+        path: path.to_string(),
+        headers: Default::default(),
+        body: vec![],
+        version: "HTTP 1.1".to_string(),
+        query: HashMap::new(),
+        cookies: None,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn handler_runs_inside_the_middleware_chain() {
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add(timer::Timer);
+    dispatcher.set_handler(|req: Request| async move {
+        Response::new().status(StatusCode::Ok).text(&req.path)
+    });
+
+    let res = dispatcher.dispatch(make_request("/hello")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+    assert_eq!(res.body.as_bytes().unwrap(), b"/hello");
+    assert!(res.headers.contains_key("x-duration"));
+}
+
+#[tokio::test]
+async fn middleware_can_change_handler_output() {
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add(add_header::AddHeader);
+    dispatcher.set_handler(|_| async { Response::new().status(StatusCode::Created) });
+
+    let res = dispatcher.dispatch(make_request("/")).await;
+    assert_eq!(res.status, StatusCode::Created);
+    assert_eq!(res.headers.get("x-example").unwrap(), "It works! :D");
+}
+
+#[tokio::test]
+async fn middleware_short_circuits_without_calling_next() {
+    let called = Arc::new(Mutex::new(false));
+    let called_clone = called.clone();
+
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add(auth::Auth);
+    dispatcher.set_handler(move |_| {
+        let called = called_clone.clone();
+        async move {
+            *called.lock().unwrap() = true;
+            Response::new().status(StatusCode::Ok)
+        }
+    });
+
+    let res = dispatcher.dispatch(make_request("/")).await;
+    assert_eq!(res.status, StatusCode::Unauthorized);
+    assert_eq!(res.body.as_bytes().unwrap(), b"401 Unauthorized");
+    assert!(!*called.lock().unwrap());
+}
+
+#[tokio::test]
+async fn app_state_reaches_middleware_and_handlers() {
+    use crate::core::{extract::State, router::Router};
+
+    struct AppName(&'static str);
+
+    struct RequireState;
+    #[async_trait]
+    impl Middleware for RequireState {
+        async fn handle(&self, req: Request, next: Next) -> Response {
+            assert_eq!(req.extensions.get::<AppName>().unwrap().0, "demo");
+            next(req).await
+        }
+    }
+
+    async fn name(State(app): State<AppName>) -> Response {
+        Response::new().status(StatusCode::Ok).text(&app.0)
+    }
+
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add_state(AppName("demo"));
+    dispatcher.add(RequireState);
+    dispatcher.set_router(Router::new().route(Method::GET, "/", name));
+
+    let res = dispatcher.dispatch(make_request("/")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+    assert_eq!(res.body.as_bytes().unwrap(), b"demo");
+}
+
+#[tokio::test]
+async fn session_tracker_attaches_the_session() {
+    use crate::core::extract::Extension;
+    use session_tracker::Session;
+
+    async fn whoami(Extension(session): Extension<Session>) -> Response {
+        Response::new().status(StatusCode::Ok).text(&session.id)
+    }
+
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add(session_tracker::SessionTracker::new());
+    dispatcher.set_router(crate::core::router::Router::new().route(Method::GET, "/", whoami));
+
+    let mut req = make_request("/");
+    req.cookies = Some(HashMap::from([("session_id".to_string(), "abc".to_string())]));
+    let res = dispatcher.dispatch(req).await;
+    assert_eq!(res.body.as_bytes().unwrap(), b"abc");
+    assert!(!res.headers.contains_key("Set-Cookie"));
+
+    // Without a cookie the minted id is the one the client is told to keep
+    let res = dispatcher.dispatch(make_request("/")).await;
+    let id = std::str::from_utf8(res.body.as_bytes().unwrap()).unwrap().to_string();
+    assert_eq!(res.headers.get("Set-Cookie").unwrap(), format!("session_id={}", id));
+}
+
+#[tokio::test]
+async fn conditional_middleware_only_runs_when_matched() {
+    use conditional::Condition;
+
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add_when(Condition::path_prefix("/admin"), auth::Auth);
+    dispatcher.add_when(Condition::path_prefix("/static/").not(), add_header::AddHeader);
+    dispatcher.set_handler(|_| async { Response::new().status(StatusCode::Ok) });
+
+    let res = dispatcher.dispatch(make_request("/admin/users")).await;
+    assert_eq!(res.status, StatusCode::Unauthorized);
+    let res = dispatcher.dispatch(make_request("/admin")).await;
+    assert_eq!(res.status, StatusCode::Unauthorized);
+
+    let res = dispatcher.dispatch(make_request("/administrator")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+    assert!(res.headers.contains_key("x-example"));
+
+    let res = dispatcher.dispatch(make_request("/static/app.js")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+    assert!(!res.headers.contains_key("x-example"));
+}
+
+#[tokio::test]
+async fn extra_slashes_cannot_skip_a_path_condition() {
+    use conditional::Condition;
+
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add_when(Condition::path_prefix("/admin"), auth::Auth);
+    dispatcher.set_router(crate::core::router::Router::new().route(Method::GET, "/admin/secret", || async {
+        Response::new().status(StatusCode::Ok).text(&"secret")
+    }));
+
+    assert_eq!(dispatcher.dispatch(make_request("/admin/secret")).await.status, StatusCode::Unauthorized);
+    for path in ["//admin/secret", "///admin/secret"] {
+        let res = dispatcher.dispatch(make_request(path)).await;
+        assert_eq!(res.status, StatusCode::NotFound, "{}", path);
+    }
+}
+
+#[test]
+fn conditions_combine() {
+    use conditional::Condition;
+
+    let mut req = make_request("/api/items");
+    req.method = Method::POST;
+    req.headers.insert("Host", "API.example.com:8443");
+
+    assert!(Condition::host("api.example.com").matches(&req));
+    assert!(!Condition::host("example.com").matches(&req));
+    assert!(Condition::method(Method::POST).and(Condition::path_prefix("/api")).matches(&req));
+    assert!(!Condition::method(Method::GET).and(Condition::path_prefix("/api")).matches(&req));
+    assert!(Condition::method(Method::GET).or(Condition::predicate(|r| r.path.ends_with("items"))).matches(&req));
+    assert!(!Condition::path_prefix("/api").not().matches(&req));
+}
+
+fn cors_dispatcher(cors: cors::Cors) -> (Dispatcher, Arc<Mutex<bool>>) {
+    let called = Arc::new(Mutex::new(false));
+    let called_clone = called.clone();
+
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add(cors);
+    dispatcher.set_handler(move |_| {
+        let called = called_clone.clone();
+        async move {
+            *called.lock().unwrap() = true;
+            Response::new().status(StatusCode::Ok).header("Vary", "Accept-Encoding")
+        }
+    });
+    (dispatcher, called)
+}
+
+fn cors_request(method: Method, origin: &str) -> Request {
+    let mut req = make_request("/api");
+    req.method = method;
+    req.headers.insert("Origin", origin);
+    req
+}
+
+#[tokio::test]
+async fn cors_preflight_is_answered_without_the_handler() {
+    let cors = cors::Cors::new()
+        .allow_origin("https://app.example.com")
+        .allow_methods([Method::GET, Method::PUT])
+        .allow_headers(["Content-Type", "X-Token"])
+        .allow_credentials(true)
+        .max_age(std::time::Duration::from_secs(600));
+    let (dispatcher, called) = cors_dispatcher(cors);
+
+    let mut req = cors_request(Method::OPTIONS, "https://app.example.com");
+    req.headers.insert("Access-Control-Request-Method", "PUT");
+    let res = dispatcher.dispatch(req).await;
+
+    assert!(!*called.lock().unwrap());
+    assert_eq!(res.status, StatusCode::NoContent);
+    assert_eq!(res.headers.get("access-control-allow-origin").unwrap(), "https://app.example.com");
+    assert_eq!(res.headers.get("access-control-allow-methods").unwrap(), "GET, PUT");
+    assert_eq!(res.headers.get("access-control-allow-headers").unwrap(), "Content-Type, X-Token");
+    assert_eq!(res.headers.get("access-control-allow-credentials").unwrap(), "true");
+    assert_eq!(res.headers.get("access-control-max-age").unwrap(), "600");
+    assert_eq!(res.headers.get("vary").unwrap(), "Origin, Access-Control-Request-Method, Access-Control-Request-Headers");
+
+    let mut req = cors_request(Method::OPTIONS, "https://app.example.com");
+    req.headers.insert("Access-Control-Request-Method", "DELETE");
+    let res = dispatcher.dispatch(req).await;
+    assert_eq!(res.status, StatusCode::NoContent);
+    assert!(!res.headers.contains_key("access-control-allow-origin"));
+}
+
+#[tokio::test]
+async fn cors_actual_request_gets_headers_for_allowed_origins_only() {
+    let cors = cors::Cors::new()
+        .allow_origin("https://*.example.com")
+        .expose_headers(["X-Request-Id"]);
+    let (dispatcher, called) = cors_dispatcher(cors);
+
+    let res = dispatcher.dispatch(cors_request(Method::GET, "https://app.example.com")).await;
+    assert!(*called.lock().unwrap());
+    assert_eq!(res.headers.get("access-control-allow-origin").unwrap(), "https://app.example.com");
+    assert_eq!(res.headers.get("access-control-expose-headers").unwrap(), "X-Request-Id");
+    assert_eq!(res.headers.get("vary").unwrap(), "Accept-Encoding, Origin");
+
+    let res = dispatcher.dispatch(cors_request(Method::GET, "https://evil.com")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+    assert!(!res.headers.contains_key("access-control-allow-origin"));
+    assert_eq!(res.headers.get("vary").unwrap(), "Accept-Encoding, Origin");
+}
+
+#[tokio::test]
+async fn cors_any_origin() {
+    let (dispatcher, _) = cors_dispatcher(cors::Cors::new().allow_any_origin());
+    let res = dispatcher.dispatch(cors_request(Method::GET, "https://a.test")).await;
+    assert_eq!(res.headers.get("access-control-allow-origin").unwrap(), "*");
+    assert_eq!(res.headers.get("vary").unwrap(), "Accept-Encoding");
+
+    // "*" isn't allowed with credentials, so the origin is echoed back
+    let (dispatcher, _) = cors_dispatcher(cors::Cors::new().allow_any_origin().allow_credentials(true));
+    let res = dispatcher.dispatch(cors_request(Method::GET, "https://a.test")).await;
+    assert_eq!(res.headers.get("access-control-allow-origin").unwrap(), "https://a.test");
+    assert_eq!(res.headers.get("vary").unwrap(), "Accept-Encoding, Origin");
+
+    let (dispatcher, _) = cors_dispatcher(cors::Cors::new().allow_origin_fn(|o| o.ends_with(".test")));
+    let res = dispatcher.dispatch(cors_request(Method::GET, "https://b.test")).await;
+    assert_eq!(res.headers.get("access-control-allow-origin").unwrap(), "https://b.test");
+}
+
+fn rate_limited(limit: rate_limit::RateLimit) -> Dispatcher {
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add(limit);
+    dispatcher.set_handler(|_| async { Response::new().status(StatusCode::Ok) });
+    dispatcher
+}
+
+fn request_from(ip: &str) -> Request {
+    let mut req = make_request("/");
+    req.connection = Arc::new(crate::core::connection_info::ConnectionInfo {
+        peer_addr: Some(format!("{}:4000", ip).parse().unwrap()),
+        ..Default::default()
+    });
+    req
+}
+
+#[tokio::test]
+async fn rate_limit_rejects_once_the_burst_is_spent() {
+    let dispatcher = rate_limited(rate_limit::RateLimit::new(1, std::time::Duration::from_secs(60)).burst(2));
+
+    let res = dispatcher.dispatch(request_from("10.0.0.1")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+    assert_eq!(res.headers.get("ratelimit-limit").unwrap(), "2");
+    assert_eq!(res.headers.get("ratelimit-remaining").unwrap(), "1");
+    assert_eq!(res.headers.get("ratelimit-reset").unwrap(), "60");
+
+    let res = dispatcher.dispatch(request_from("10.0.0.1")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+    assert_eq!(res.headers.get("ratelimit-remaining").unwrap(), "0");
+
+    let res = dispatcher.dispatch(request_from("10.0.0.1")).await;
+    assert_eq!(res.status, StatusCode::TooManyRequests);
+    assert_eq!(res.headers.get("retry-after").unwrap(), "60");
+    assert_eq!(res.headers.get("ratelimit-remaining").unwrap(), "0");
+
+    // Other clients have their own bucket
+    let res = dispatcher.dispatch(request_from("10.0.0.2")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+}
+
+#[tokio::test]
+async fn rate_limit_refills_over_time() {
+    let dispatcher = rate_limited(rate_limit::RateLimit::new(20, std::time::Duration::from_secs(1)).burst(1));
+
+    assert_eq!(dispatcher.dispatch(request_from("10.0.0.1")).await.status, StatusCode::Ok);
+    assert_eq!(dispatcher.dispatch(request_from("10.0.0.1")).await.status, StatusCode::TooManyRequests);
+    tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+    assert_eq!(dispatcher.dispatch(request_from("10.0.0.1")).await.status, StatusCode::Ok);
+}
+
+#[tokio::test]
+async fn rate_limit_by_header_skips_requests_without_it() {
+    let dispatcher = rate_limited(rate_limit::RateLimit::new(1, std::time::Duration::from_secs(60)).key_by_header("X-Api-Key"));
+
+    let mut req = make_request("/");
+    req.headers.insert("X-Api-Key", "abc");
+    assert_eq!(dispatcher.dispatch(req.clone()).await.status, StatusCode::Ok);
+    assert_eq!(dispatcher.dispatch(req).await.status, StatusCode::TooManyRequests);
+
+    let res = dispatcher.dispatch(make_request("/")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+    assert!(!res.headers.contains_key("ratelimit-limit"));
+}
+
+#[tokio::test]
+async fn rate_limit_by_session_falls_back_to_the_peer_without_a_cookie() {
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add(session_tracker::SessionTracker::new());
+    dispatcher.add(rate_limit::RateLimit::new(1, std::time::Duration::from_secs(60)).key_by_session());
+    dispatcher.set_handler(|_| async { Response::new().status(StatusCode::Ok) });
+
+    // A new session id every time, but still one bucket per client
+    let res = dispatcher.dispatch(request_from("10.0.0.1")).await;
+    assert_eq!(res.status, StatusCode::Ok);
+    assert_eq!(dispatcher.dispatch(request_from("10.0.0.1")).await.status, StatusCode::TooManyRequests);
+
+    // Sending back the cookie it was given puts the client in its session's bucket
+    let id = res.headers.get("Set-Cookie").unwrap().strip_prefix("session_id=").unwrap().to_string();
+    let mut req = request_from("10.0.0.1");
+    req.cookies = Some(HashMap::from([("session_id".to_string(), id)]));
+    assert_eq!(dispatcher.dispatch(req.clone()).await.status, StatusCode::Ok);
+    assert_eq!(dispatcher.dispatch(req).await.status, StatusCode::TooManyRequests);
+}
+
+#[test]
+#[should_panic(expected = "at least one request")]
+fn rate_limit_rejects_a_zero_rate() {
+    rate_limit::RateLimit::new(0, std::time::Duration::from_secs(1));
+}
+
+#[test]
+#[should_panic(expected = "burst must be at least 1")]
+fn rate_limit_rejects_a_zero_burst() {
+    rate_limit::RateLimit::new(1, std::time::Duration::from_secs(1)).burst(0);
+}
+
+#[tokio::test]
+async fn hsts_is_only_sent_over_tls() {
+    use crate::core::connection_info::{ConnectionInfo, TlsInfo};
+
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add(hsts::Hsts::new(std::time::Duration::from_secs(31536000)).include_subdomains());
+    dispatcher.set_handler(|_| async { Response::new().status(StatusCode::Ok) });
+
+    let res = dispatcher.dispatch(make_request("/")).await;
+    assert!(!res.headers.contains_key("strict-transport-security"));
+
+    let mut req = make_request("/");
+    req.connection = Arc::new(ConnectionInfo { tls: Some(TlsInfo::default()), ..Default::default() });
+    let res = dispatcher.dispatch(req).await;
+    assert_eq!(res.headers.get("strict-transport-security").unwrap(), "max-age=31536000; includeSubDomains");
+}
@@ -1,6 +1,7 @@
-use http::middleware::{logger::Logger, session_tracker::SessionTracker, set_cookie::SetCookie, timer::Timer, Dispatcher};
-use pool::thread_pool::ThreadPool;
-use tokio::{net::TcpListener, runtime};
+
+use handlers::redirect::https_redirect;
+use http::middleware::{conditional::Condition, hsts::Hsts, logger::Logger, session_tracker::SessionTracker, set_cookie::SetCookie, timer::Timer, Dispatcher};
+use tokio::{net::TcpListener, sync::watch, task::JoinSet};
 use tokio_rustls::{TlsAcceptor, rustls::ServerConfig};
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, Layer };
@@ -9,14 +10,19 @@ use tracing_appender::rolling;
 mod core;
 mod http;
 mod handlers;
-mod pool;
 
-use core::{connection::handle_client, tls::load_certs_and_key};
-use std::sync::Arc;
+use core::{
+    config::{ConnectionConfig, ListenerConfig, Protocol, Service},
+    connection::{handle_client, shutdown_started},
+    connection_info::ConnectionInfo,
+    router::routes,
+    tls::load_certs_and_key,
+};
+use std::{sync::Arc, time::Duration};
 
 #[tokio::main] async fn main() -> std::io::Result<()>{
     let file_appender = rolling::daily("logs", "server.log");
-    let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
+    let (non_blocking, log_guard) = tracing_appender::non_blocking(file_appender);
 
     let file_layer = fmt::Layer::default()
         .json()
@@ -29,43 +35,165 @@ use std::sync::Arc;
         .init();
 
     dotenvy::dotenv().ok();
-    let address = std::env::var("BIND_ADDRESS").expect("BIND_ADDRESS not set");
-
-    println!("Listening on {:?}", &address);
-    let listener = TcpListener::bind(address).await?;
+    let listeners = ListenerConfig::from_env();
 
-    let (tls_cert, tls_key) = load_certs_and_key().unwrap();
-    let tls_conf = ServerConfig::builder().with_no_client_auth().with_single_cert(tls_cert, tls_key).unwrap();
-    let acceptor = TlsAcceptor::from(Arc::new(tls_conf));
+    // Certificates are only needed when we terminate TLS ourselves
+    let acceptor = if listeners.iter().any(|l| l.protocol == Protocol::Tls) {
+        let (tls_cert, tls_key) = load_certs_and_key().unwrap();
+        let tls_conf = ServerConfig::builder().with_no_client_auth().with_single_cert(tls_cert, tls_key).unwrap();
+        Some(TlsAcceptor::from(Arc::new(tls_conf)))
+    } else {
+        None
+    };
 
-    let runtime = runtime::Runtime::new().unwrap();
-    let handle = runtime.handle().clone();
-
-    let threadpool = ThreadPool::new(10, handle);
+    // Redirect listeners point at the first TLS app listener's port
+    let https_port = listeners.iter()
+        .find(|l| l.protocol == Protocol::Tls && l.service == Service::App)
+        .and_then(|l| l.address.rsplit(':').next()?.parse().ok())
+        .unwrap_or(443);
 
     let mut dispatcher = Dispatcher::new();
+    dispatcher.set_router(routes());
     dispatcher.add(Logger::new());
     dispatcher.add(Timer);
     dispatcher.add(SetCookie);
     let sessions = SessionTracker::new();
-    dispatcher.add(sessions);
+    dispatcher.add_when(Condition::path_prefix("/static").not(), sessions);
+    if let Some(max_age) = std::env::var("HSTS_MAX_AGE").ok().and_then(|v| v.trim().parse().ok()) {
+        dispatcher.add(Hsts::new(Duration::from_secs(max_age)));
+    }
 
-    let dispatcher_arc = Arc::new(dispatcher);
+    // Redirect listeners share the middleware, but never reach the router
+    let mut redirect = dispatcher.clone();
+    redirect.set_handler(move |req| async move { https_redirect(&req, https_port) });
 
-    while let Ok((socket, _addr)) = listener.accept().await {
-        let tls_stream = match acceptor.accept(socket).await {
-            Ok(stream) => stream,
-            Err(e) => {
-                eprintln!("TLS Handshake failed: {}", e);
-                continue;
-            }
+    let app = Arc::new(dispatcher);
+    let redirect = Arc::new(redirect);
+    let config = Arc::new(ConnectionConfig::from_env());
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let mut accept_loops = vec![];
+    for listener_config in listeners {
+        let listener = TcpListener::bind(&listener_config.address).await?;
+        println!("Listening on {:?} ({:?}, {:?})", &listener_config.address, listener_config.protocol, listener_config.service);
+
+        let dispatcher = match listener_config.service {
+            Service::App => app.clone(),
+            Service::Redirect => redirect.clone(),
         };
-        let dispatcher_clone = dispatcher_arc.clone();
-        threadpool.enqueue(move || async move {
-            handle_client(tls_stream, dispatcher_clone).await;
-        });
-        
+        let acceptor = match listener_config.protocol {
+            Protocol::Tls => acceptor.clone(),
+            Protocol::Plain => None,
+        };
+        accept_loops.push(tokio::spawn(accept_loop(listener, acceptor, dispatcher, config.clone(), shutdown_rx.clone())));
+    }
+
+    shutdown_signal().await;
+    println!("Shutting down, waiting up to {:?} for open connections", config.shutdown_timeout);
+
+    // Listeners stop accepting, idle connections close, busy ones close after their current response
+    let _ = shutdown_tx.send(true);
+    let mut connections = vec![];
+    for accept_loop in accept_loops {
+        if let Ok(open) = accept_loop.await {
+            connections.push(open);
+        }
     }
 
+    let drained = tokio::time::timeout(config.shutdown_timeout, async {
+        for open in connections.iter_mut() {
+            while open.join_next().await.is_some() {}
+        }
+    }).await;
+    if drained.is_err() {
+        // Dropping the sets aborts whatever is left
+        eprintln!("Connections still open after {:?}, closing them", config.shutdown_timeout);
+    }
+    drop(connections);
+
+    // Flushes any buffered log lines
+    drop(log_guard);
+
     Ok(())
 }
+
+// Resolves on SIGINT (Ctrl+C) or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => { signal.recv().await; }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+// Accepts connections on one listener and runs each as its own task, so an idle
+// keep-alive connection costs a task rather than a thread. `acceptor` is None for
+// cleartext listeners. Returns the connections still open once shutdown starts.
+async fn accept_loop(
+    listener: TcpListener,
+    acceptor: Option<TlsAcceptor>,
+    dispatcher: Arc<Dispatcher>,
+    config: Arc<ConnectionConfig>,
+    mut shutdown: watch::Receiver<bool>,
+) -> JoinSet<()> {
+    let mut connections = JoinSet::new();
+    loop {
+        let socket = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((socket, _addr)) => socket,
+                // Usually transient, like running out of file descriptors under load,
+                // so the listener backs off instead of going quiet for good
+                Err(e) => {
+                    eprintln!("Error accepting connection: {}", e);
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_millis(100)) => continue,
+                        _ = shutdown_started(&mut shutdown) => break,
+                    }
+                }
+            },
+            // Reaps finished connections so the set doesn't grow forever
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            _ = shutdown_started(&mut shutdown) => break,
+        };
+        let dispatcher_clone = dispatcher.clone();
+        let config_clone = config.clone();
+        let shutdown_clone = shutdown.clone();
+
+        let Some(acceptor) = acceptor.clone() else {
+            let info = ConnectionInfo::from_tcp(&socket);
+            connections.spawn(handle_client(socket, info, dispatcher_clone, config_clone, shutdown_clone));
+            continue;
+        };
+
+        // The handshake runs in the connection's task, so a slow client can't hold up the accept loop
+        connections.spawn(async move {
+            let tls_stream = match tokio::time::timeout(config_clone.handshake_timeout, acceptor.accept(socket)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    eprintln!("TLS Handshake failed: {}", e);
+                    return;
+                }
+                Err(_) => {
+                    eprintln!("TLS Handshake timed out");
+                    return;
+                }
+            };
+            let info = ConnectionInfo::from_tls(&tls_stream);
+            handle_client(tls_stream, info, dispatcher_clone, config_clone, shutdown_clone).await;
+        });
+    }
+    connections
+}
@@ -1 +0,0 @@
-pub mod thread_pool;